@@ -0,0 +1,104 @@
+use crate::ray::Ray;
+use crate::vector3::Vector3;
+
+/// An axis-aligned bounding box
+#[derive(Copy, Clone)]
+pub struct Aabb {
+    min: Vector3,
+    max: Vector3,
+}
+
+impl Aabb {
+    /// Creates a new bounding box from its minimum and maximum corners
+    pub fn new(min: Vector3, max: Vector3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    /// Returns a bounding box containing nothing (growing
+    /// it by anything results in that thing's bounds)
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Vector3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Vector3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    /// Returns the minimum corner of the box
+    pub fn min(self) -> Vector3 {
+        self.min
+    }
+
+    /// Returns the maximum corner of the box
+    pub fn max(self) -> Vector3 {
+        self.max
+    }
+
+    /// Returns the smallest box containing both boxes
+    pub fn union(self, other: &Aabb) -> Aabb {
+        Aabb::new(
+            Vector3::new(
+                self.min[0].min(other.min[0]),
+                self.min[1].min(other.min[1]),
+                self.min[2].min(other.min[2]),
+            ),
+            Vector3::new(
+                self.max[0].max(other.max[0]),
+                self.max[1].max(other.max[1]),
+                self.max[2].max(other.max[2]),
+            ),
+        )
+    }
+
+    /// Returns the smallest box containing both the box and a point
+    pub fn grow(self, point: &Vector3) -> Aabb {
+        self.union(&Aabb::new(*point, *point))
+    }
+
+    /// Returns the center of the box
+    pub fn centroid(self) -> Vector3 {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Returns the size of the box along each axis
+    pub fn extent(self) -> Vector3 {
+        self.max - self.min
+    }
+
+    /// Returns the surface area of the box (0 for an empty box)
+    pub fn surface_area(self) -> f64 {
+        let extent = self.extent();
+
+        if extent[0] < 0.0 || extent[1] < 0.0 || extent[2] < 0.0 {
+            return 0.0;
+        }
+
+        2.0 * (extent[0] * extent[1] + extent[1] * extent[2] + extent[2] * extent[0])
+    }
+
+    /// Finds the distance at which a ray enters the box, if it enters
+    /// the box before `max_distance` (`inverse_direction` is 1 divided
+    /// by each term of the ray's direction, precomputed for speed)
+    pub fn hit(&self, ray: &Ray, inverse_direction: &Vector3, max_distance: f64) -> Option<f64> {
+        let origin = ray.origin();
+
+        let mut entry_distance: f64 = 0.0;
+        let mut exit_distance = max_distance;
+
+        // clip the ray's distance range by each pair of planes (slabs)
+        for axis in 0..3 {
+            let near = (self.min[axis] - origin[axis]) * inverse_direction[axis];
+            let far = (self.max[axis] - origin[axis]) * inverse_direction[axis];
+
+            // f64::min and f64::max ignore NaN, which shows up when
+            // the ray starts exactly on a plane it runs parallel to
+            entry_distance = entry_distance.max(near.min(far));
+            exit_distance = exit_distance.min(near.max(far));
+        }
+
+        if entry_distance <= exit_distance {
+            Some(entry_distance)
+        } else {
+            None
+        }
+    }
+}
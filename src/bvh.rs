@@ -0,0 +1,447 @@
+use crate::aabb::Aabb;
+use crate::hit::Hit;
+use crate::objects::Object;
+use crate::ray::Ray;
+use crate::vector3::Vector3;
+
+// the number of buckets centroids are sorted into when looking for a split
+const SAH_BIN_COUNT: usize = 12;
+
+// the relative cost of visiting a node compared to hitting an object
+const SAH_TRAVERSAL_COST: f64 = 1.0;
+
+// the most objects a leaf will hold if splitting isn't worth it
+const MAX_LEAF_SIZE: usize = 8;
+
+// the deepest the hierarchy can get (bounds the traversal stack)
+const MAX_DEPTH: usize = 64;
+
+/// A node of the hierarchy
+enum BvhNode {
+    /// Holds the objects from `start` up to (not including) `end`
+    Leaf {
+        bounding_box: Aabb,
+        start: usize,
+        end: usize,
+    },
+
+    /// Holds the indices of two child nodes
+    Interior {
+        bounding_box: Aabb,
+        left: usize,
+        right: usize,
+    },
+}
+
+impl BvhNode {
+    /// Returns the bounding box of the node
+    fn bounding_box(&self) -> Aabb {
+        match self {
+            BvhNode::Leaf { bounding_box, .. } => *bounding_box,
+            BvhNode::Interior { bounding_box, .. } => *bounding_box,
+        }
+    }
+}
+
+/// Information about an object used while building the hierarchy
+#[derive(Copy, Clone)]
+struct BuildItem {
+    index: usize,
+    bounding_box: Aabb,
+    centroid: Vector3,
+}
+
+/// A bounding volume hierarchy built with the surface area heuristic,
+/// which speeds up finding the closest object a ray hits
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    objects: Vec<Box<dyn Object + Send + Sync>>,
+}
+
+impl Bvh {
+    /// Builds a hierarchy containing the given objects
+    pub fn new(objects: Vec<Box<dyn Object + Send + Sync>>) -> Bvh {
+        let mut items: Vec<BuildItem> = objects
+            .iter()
+            .enumerate()
+            .map(|(index, object)| {
+                let bounding_box = object.bounding_box();
+
+                BuildItem {
+                    index,
+                    bounding_box,
+                    centroid: bounding_box.centroid(),
+                }
+            })
+            .collect();
+
+        let mut nodes = Vec::new();
+        if !items.is_empty() {
+            Bvh::build_node(&mut items, 0, 0, &mut nodes);
+        }
+
+        // reorder the objects so every leaf refers to a contiguous range
+        let mut unordered_objects: Vec<Option<Box<dyn Object + Send + Sync>>> =
+            objects.into_iter().map(Some).collect();
+        let objects = items
+            .iter()
+            .map(|item| unordered_objects[item.index].take().unwrap())
+            .collect();
+
+        Bvh { nodes, objects }
+    }
+
+    /// Recursively builds the node for a range of items (which start at `offset`
+    /// in the full list) and returns the node's index
+    fn build_node(
+        items: &mut [BuildItem],
+        offset: usize,
+        depth: usize,
+        nodes: &mut Vec<BvhNode>,
+    ) -> usize {
+        let bounding_box = items.iter().fold(Aabb::empty(), |bounds, item| {
+            bounds.union(&item.bounding_box)
+        });
+
+        // reserve this node's spot before its children are added
+        let node_index = nodes.len();
+        nodes.push(BvhNode::Leaf {
+            bounding_box,
+            start: offset,
+            end: offset + items.len(),
+        });
+
+        if depth + 1 >= MAX_DEPTH {
+            return node_index;
+        }
+
+        let split = match Bvh::find_split(items, &bounding_box) {
+            Some(split) => split,
+            None => return node_index,
+        };
+
+        let (left_items, right_items) = items.split_at_mut(split);
+        let left = Bvh::build_node(left_items, offset, depth + 1, nodes);
+        let right = Bvh::build_node(right_items, offset + split, depth + 1, nodes);
+
+        nodes[node_index] = BvhNode::Interior {
+            bounding_box,
+            left,
+            right,
+        };
+
+        node_index
+    }
+
+    /// Partitions the items along the cheapest split according to the
+    /// surface area heuristic, returning how many items went on the left
+    /// (or None if the items are cheaper to keep together in a leaf)
+    fn find_split(items: &mut [BuildItem], bounding_box: &Aabb) -> Option<usize> {
+        if items.len() <= 1 {
+            return None;
+        }
+
+        let centroid_bounds = items
+            .iter()
+            .fold(Aabb::empty(), |bounds, item| bounds.grow(&item.centroid));
+        let centroid_extent = centroid_bounds.extent();
+
+        // stores the cheapest (cost, axis, bin) found so far
+        let mut best_split: Option<(f64, usize, usize)> = None;
+
+        for axis in 0..3 {
+            if centroid_extent[axis] <= 0.0 {
+                continue;
+            }
+
+            // sort the items into buckets along the axis
+            let mut bin_boxes = [Aabb::empty(); SAH_BIN_COUNT];
+            let mut bin_counts = [0usize; SAH_BIN_COUNT];
+
+            for item in items.iter() {
+                let bin = Bvh::bin_of(item, &centroid_bounds, axis);
+                bin_boxes[bin] = bin_boxes[bin].union(&item.bounding_box);
+                bin_counts[bin] += 1;
+            }
+
+            // sweep from the right to get the cost of everything right of each split
+            let mut right_costs = [0.0; SAH_BIN_COUNT];
+            let mut right_box = Aabb::empty();
+            let mut right_count = 0;
+
+            for bin in (1..SAH_BIN_COUNT).rev() {
+                right_box = right_box.union(&bin_boxes[bin]);
+                right_count += bin_counts[bin];
+                right_costs[bin] = right_box.surface_area() * right_count as f64;
+            }
+
+            // sweep from the left and combine with the right costs
+            let mut left_box = Aabb::empty();
+            let mut left_count = 0;
+
+            for bin in 1..SAH_BIN_COUNT {
+                left_box = left_box.union(&bin_boxes[bin - 1]);
+                left_count += bin_counts[bin - 1];
+
+                let cost = SAH_TRAVERSAL_COST
+                    + (left_box.surface_area() * left_count as f64 + right_costs[bin])
+                        / bounding_box.surface_area();
+
+                if best_split.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                    best_split = Some((cost, axis, bin));
+                }
+            }
+        }
+
+        // every centroid is in the same spot, so there's no way to split them
+        let (cost, axis, split_bin) = match best_split {
+            Some(best_split) => best_split,
+            None if items.len() <= MAX_LEAF_SIZE => return None,
+            None => return Some(items.len() / 2),
+        };
+
+        // keep the items in a leaf if that's cheaper than splitting them
+        if cost >= items.len() as f64 && items.len() <= MAX_LEAF_SIZE {
+            return None;
+        }
+
+        // move every item left of the split to the front
+        let mut split = 0;
+        for index in 0..items.len() {
+            if Bvh::bin_of(&items[index], &centroid_bounds, axis) < split_bin {
+                items.swap(index, split);
+                split += 1;
+            }
+        }
+
+        if split == 0 || split == items.len() {
+            return Some(items.len() / 2);
+        }
+
+        Some(split)
+    }
+
+    /// Returns which bin an item's centroid falls in along an axis
+    fn bin_of(item: &BuildItem, centroid_bounds: &Aabb, axis: usize) -> usize {
+        let relative_position =
+            (item.centroid[axis] - centroid_bounds.min()[axis]) / centroid_bounds.extent()[axis];
+
+        ((relative_position * SAH_BIN_COUNT as f64) as usize).min(SAH_BIN_COUNT - 1)
+    }
+}
+
+impl Object for Bvh {
+    fn get_hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        let direction = ray.direction();
+        let inverse_direction =
+            Vector3::new(1.0 / direction[0], 1.0 / direction[1], 1.0 / direction[2]);
+
+        // keeps track of the closest hit to the ray
+        let mut closest_hit: Option<Hit> = None;
+        let mut closest_distance = f64::INFINITY;
+
+        // nodes left to visit along with the distance the ray enters them at
+        let mut stack = [(0usize, 0.0f64); MAX_DEPTH];
+        let mut stack_size = 0;
+
+        if let Some(root) = self.nodes.first() {
+            if let Some(distance) = root
+                .bounding_box()
+                .hit(ray, &inverse_direction, f64::INFINITY)
+            {
+                stack[0] = (0, distance);
+                stack_size = 1;
+            }
+        }
+
+        while stack_size > 0 {
+            stack_size -= 1;
+            let (node_index, entry_distance) = stack[stack_size];
+
+            // skip nodes that are only reached after the closest hit
+            if entry_distance > closest_distance {
+                continue;
+            }
+
+            match &self.nodes[node_index] {
+                BvhNode::Leaf { start, end, .. } => {
+                    if let Some(hit) = ray.get_hit(&self.objects[*start..*end]) {
+                        if hit.distance < closest_distance {
+                            closest_distance = hit.distance;
+                            closest_hit = Some(hit);
+                        }
+                    }
+                }
+
+                BvhNode::Interior { left, right, .. } => {
+                    let left_hit = self.nodes[*left].bounding_box().hit(
+                        ray,
+                        &inverse_direction,
+                        closest_distance,
+                    );
+                    let right_hit = self.nodes[*right].bounding_box().hit(
+                        ray,
+                        &inverse_direction,
+                        closest_distance,
+                    );
+
+                    // push the farther child first so the nearer one is visited first
+                    let mut children = [(*left, left_hit), (*right, right_hit)];
+                    if let (Some(left_distance), Some(right_distance)) = (left_hit, right_hit) {
+                        if left_distance < right_distance {
+                            children.swap(0, 1);
+                        }
+                    }
+
+                    for (child, child_hit) in children {
+                        if let Some(distance) = child_hit {
+                            stack[stack_size] = (child, distance);
+                            stack_size += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        closest_hit
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        match self.nodes.first() {
+            Some(root) => root.bounding_box(),
+            None => Aabb::empty(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sampler::{IndependentSampler, Sampler};
+    use crate::sphere::Sphere;
+    use crate::triangle::Triangle;
+    use std::sync::Arc;
+
+    type Objects = Vec<Box<dyn Object + Send + Sync>>;
+
+    /// Returns a random point inside a cube `size` units wide centered on the origin
+    fn random_point(sampler: &mut dyn Sampler, size: f64) -> Vector3 {
+        Vector3::new(
+            (sampler.get_1d() - 0.5) * size,
+            (sampler.get_1d() - 0.5) * size,
+            (sampler.get_1d() - 0.5) * size,
+        )
+    }
+
+    /// Builds `count` random spheres and triangles, where the same seed always
+    /// gives the same objects
+    fn random_objects(count: usize, seed: u64) -> Objects {
+        let mut sampler = IndependentSampler::new(seed);
+        let material = Arc::new(Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
+
+        (0..count)
+            .map(|_| -> Box<dyn Object + Send + Sync> {
+                let center = random_point(&mut sampler, 20.0);
+
+                if sampler.get_1d() < 0.5 {
+                    let radius = 0.1 + sampler.get_1d();
+                    Box::new(Sphere::new(center, radius, material.clone()))
+                } else {
+                    let vertices = [
+                        center + random_point(&mut sampler, 3.0),
+                        center + random_point(&mut sampler, 3.0),
+                        center + random_point(&mut sampler, 3.0),
+                    ];
+                    Box::new(Triangle::new(vertices, material.clone()))
+                }
+            })
+            .collect()
+    }
+
+    /// Builds `count` spheres and triangles that all have their centroid at `center`
+    fn objects_sharing_centroid(count: usize, center: Vector3) -> Objects {
+        let material = Arc::new(Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
+
+        (0..count)
+            .map(|index| -> Box<dyn Object + Send + Sync> {
+                let size = 0.5 + index as f64 * 0.25;
+
+                if index % 2 == 0 {
+                    Box::new(Sphere::new(center, size, material.clone()))
+                } else {
+                    // the bounding box of this triangle is centered on `center`
+                    let vertices = [
+                        center + Vector3::new(-size, -size, -size),
+                        center + Vector3::new(size, -size, size),
+                        center + Vector3::new(0.0, size, 0.0),
+                    ];
+                    Box::new(Triangle::new(vertices, material.clone()))
+                }
+            })
+            .collect()
+    }
+
+    /// Fires random rays at the objects made by `build` and checks that the
+    /// hierarchy finds the same closest hit as testing every object
+    fn assert_matches_brute_force(build: impl Fn() -> Objects, seed: u64) {
+        let objects = build();
+        let bvh = Bvh::new(build());
+        let mut sampler = IndependentSampler::new(seed);
+
+        for _ in 0..5000 {
+            let origin = random_point(&mut sampler, 40.0);
+            let direction = random_point(&mut sampler, 2.0);
+            if direction.length_squared() == 0.0 {
+                continue;
+            }
+            let ray = Ray::new(origin, direction);
+
+            match (bvh.get_hit(&ray), ray.get_hit(&objects)) {
+                (None, None) => (),
+                (Some(bvh_hit), Some(brute_force_hit)) => {
+                    assert!((bvh_hit.distance - brute_force_hit.distance).abs() < 1e-9);
+                    assert!((bvh_hit.point - brute_force_hit.point).length() < 1e-9);
+                }
+                (bvh_hit, brute_force_hit) => panic!(
+                    "the hierarchy hit {} but testing every object hit {}",
+                    bvh_hit.is_some(),
+                    brute_force_hit.is_some()
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn empty_scene_is_never_hit() {
+        assert_matches_brute_force(Vec::new, 1);
+    }
+
+    #[test]
+    fn single_object_matches_brute_force() {
+        assert_matches_brute_force(|| random_objects(1, 2), 3);
+    }
+
+    #[test]
+    fn random_scenes_match_brute_force() {
+        for seed in 0..8 {
+            assert_matches_brute_force(|| random_objects(500, seed), seed + 100);
+        }
+    }
+
+    #[test]
+    fn shared_centroids_match_brute_force() {
+        let center = Vector3::new(1.0, -2.0, 3.0);
+        assert_matches_brute_force(|| objects_sharing_centroid(40, center), 4);
+    }
+
+    #[test]
+    fn shared_centroids_among_other_objects_match_brute_force() {
+        let build = || {
+            let mut objects = random_objects(200, 5);
+            objects.extend(objects_sharing_centroid(40, Vector3::new(0.0, 0.0, 0.0)));
+            objects
+        };
+        assert_matches_brute_force(build, 6);
+    }
+}
@@ -1,35 +1,161 @@
-use crate::constants::{FOCAL_LENGTH, VIEWPORT_HEIGHT_VECTOR, VIEWPORT_WIDTH_VECTOR};
+use crate::random::{random_in_regular_polygon, random_in_unit_disk};
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vector3::Vector3;
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+/// The shape of a camera's aperture, which decides the shape of out of focus highlights
+#[derive(Copy, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ApertureShape {
+    #[default]
+    Disk,
+    /// A regular polygon (like a bladed aperture), with its
+    /// first corner `rotation` degrees from the right
+    Polygon {
+        sides: u32,
+        #[serde(default)]
+        rotation: f64,
+    },
+}
+
+impl ApertureShape {
+    /// Returns a random point on an aperture with a radius of 1 (z is always 0)
+    fn sample(self, sampler: &mut dyn Sampler) -> Vector3 {
+        match self {
+            ApertureShape::Disk => random_in_unit_disk(sampler),
+            ApertureShape::Polygon { sides, rotation } => {
+                random_in_regular_polygon(sides, rotation.to_radians(), sampler)
+            }
+        }
+    }
+}
+
+/// Describes the lens of a camera (an aperture of 0 is a perfect pinhole
+/// camera where everything is in focus)
+#[derive(Copy, Clone)]
+pub struct Lens {
+    pub aperture: f64,
+    pub focus_distance: f64,
+    pub shape: ApertureShape,
+}
 
 /// Represents a camera to render an image from
 #[derive(Copy, Clone)]
 pub struct Camera {
     position: Vector3,
     viewport_lower_left_corner: Vector3,
+    viewport_width_vector: Vector3,
+    viewport_height_vector: Vector3,
+    right: Vector3,
+    up: Vector3,
+    lens_radius: f64,
+    aperture_shape: ApertureShape,
 }
 
 impl Camera {
-    /// Creates a new camera from a position
-    pub fn new(position: Vector3) -> Camera {
-        Camera {
-            position,
-            viewport_lower_left_corner: position
-                - VIEWPORT_WIDTH_VECTOR / 2.0
-                - VIEWPORT_HEIGHT_VECTOR / 2.0
-                + Vector3::new(0.0, 0.0, FOCAL_LENGTH),
+    /// Creates a new camera at `look_from` pointed at `look_at`, with `up` deciding
+    /// which way is up in the image, `vertical_fov` the angle (in degrees) the image
+    /// covers from top to bottom, `aspect_ratio` the image's width over its height,
+    /// and `lens` deciding what is in focus
+    pub fn new(
+        look_from: Vector3,
+        look_at: Vector3,
+        up: Vector3,
+        vertical_fov: f64,
+        aspect_ratio: f64,
+        lens: Lens,
+    ) -> anyhow::Result<Camera> {
+        if !(vertical_fov > 0.0 && vertical_fov < 180.0) {
+            bail!(
+                "The camera's field of view must be between 0 and 180 degrees (got {})",
+                vertical_fov
+            );
         }
+
+        if lens.aperture < 0.0 {
+            bail!(
+                "The camera's aperture can't be negative (got {})",
+                lens.aperture
+            );
+        }
+
+        if lens.focus_distance <= 0.0 {
+            bail!(
+                "The camera's focus distance must be positive (got {})",
+                lens.focus_distance
+            );
+        }
+
+        if let ApertureShape::Polygon { sides, .. } = lens.shape {
+            if sides < 3 {
+                bail!(
+                    "A polygonal aperture needs at least 3 sides (got {})",
+                    sides
+                );
+            }
+        }
+
+        let forward = look_at - look_from;
+        if forward.length_squared() == 0.0 {
+            bail!("The camera can't look at the point it is placed at");
+        }
+
+        // build an orthonormal basis facing the target (with
+        // +x to the right when looking down +z with +y up)
+        let forward = forward.normalized();
+        let right = up.cross(&forward);
+        if right.length_squared() < 1e-12 {
+            bail!("The camera's up direction can't be parallel to the direction it looks");
+        }
+        let right = right.normalized();
+        let true_up = forward.cross(&right);
+
+        // size a viewport on the plane of focus to fit the field of view
+        let viewport_height = 2.0 * (vertical_fov.to_radians() / 2.0).tan() * lens.focus_distance;
+        let viewport_width = viewport_height * aspect_ratio;
+
+        let viewport_width_vector = right * viewport_width;
+        let viewport_height_vector = true_up * viewport_height;
+
+        Ok(Camera {
+            position: look_from,
+            viewport_lower_left_corner: look_from + forward * lens.focus_distance
+                - viewport_width_vector / 2.0
+                - viewport_height_vector / 2.0,
+            viewport_width_vector,
+            viewport_height_vector,
+            right,
+            up: true_up,
+            lens_radius: lens.aperture / 2.0,
+            aperture_shape: lens.shape,
+        })
     }
 
     /// Gets a ray from the camera given an f64 from 0 to 1 representing how
     /// far across the width or height the image is (0, 0 is bottom left)
-    pub fn get_ray(&self, width_progress: f64, height_progress: f64) -> Ray {
+    pub fn get_ray(
+        &self,
+        width_progress: f64,
+        height_progress: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Ray {
+        // start the ray from a random point on the lens so only
+        // things on the plane of focus end up sharp
+        let origin = if self.lens_radius > 0.0 {
+            let lens_point = self.aperture_shape.sample(sampler) * self.lens_radius;
+            self.position + self.right * lens_point.x() + self.up * lens_point.y()
+        } else {
+            self.position
+        };
+
         Ray::new(
-            self.position,
+            origin,
             self.viewport_lower_left_corner
-                + width_progress * VIEWPORT_WIDTH_VECTOR
-                + height_progress * VIEWPORT_HEIGHT_VECTOR
-                - self.position,
+                + width_progress * self.viewport_width_vector
+                + height_progress * self.viewport_height_vector
+                - origin,
         )
     }
 }
@@ -0,0 +1,208 @@
+use crate::image::Image;
+use crate::lights::LightSample;
+use crate::sampler::Sampler;
+use crate::vector3::Vector3;
+use std::f64::consts::PI;
+
+/// Represents the light coming from infinitely far away
+/// in every direction that doesn't hit an object
+pub trait Environment: Send + Sync {
+    /// Returns the light coming from a normalized direction
+    fn radiance(&self, direction: &Vector3) -> Vector3;
+
+    /// Chooses a direction to sample light from (or None
+    /// if the environment can't be sampled directly)
+    fn sample(&self, _sampler: &mut dyn Sampler) -> Option<LightSample> {
+        None
+    }
+
+    /// Returns the probability density of `sample` choosing a direction
+    fn pdf(&self, _direction: &Vector3) -> f64 {
+        0.0
+    }
+}
+
+/// A simple sky that blends between two colors based
+/// on how far up or down a ray is looking
+pub struct Gradient {
+    lower_color: Vector3,
+    upper_color: Vector3,
+}
+
+impl Gradient {
+    /// Creates a new gradient from the colors seen looking straight down and straight up
+    pub fn new(lower_color: Vector3, upper_color: Vector3) -> Gradient {
+        Gradient {
+            lower_color,
+            upper_color,
+        }
+    }
+}
+
+impl Environment for Gradient {
+    fn radiance(&self, direction: &Vector3) -> Vector3 {
+        let lerp_amount: f64 = (direction.y() + 1.0) * 0.5;
+        self.lower_color.lerp(&self.upper_color, lerp_amount)
+    }
+}
+
+/// A discrete distribution that picks indices proportional to their weights
+struct Distribution {
+    // the running total of the normalized weights, starting at 0
+    cdf: Vec<f64>,
+}
+
+impl Distribution {
+    /// Creates a new distribution (which is uniform if every weight is 0)
+    fn new(weights: &[f64]) -> Distribution {
+        let total: f64 = weights.iter().sum();
+
+        let mut cdf = Vec::with_capacity(weights.len() + 1);
+        cdf.push(0.0);
+        for (index, weight) in weights.iter().enumerate() {
+            let weight = if total > 0.0 {
+                weight / total
+            } else {
+                1.0 / weights.len() as f64
+            };
+            cdf.push(cdf[index] + weight);
+        }
+
+        Distribution { cdf }
+    }
+
+    /// Returns the chance of picking an index
+    fn probability(&self, index: usize) -> f64 {
+        self.cdf[index + 1] - self.cdf[index]
+    }
+
+    /// Picks an index from a uniform random number in [0, 1)
+    fn sample(&self, random: f64) -> usize {
+        let count = self.cdf.len() - 1;
+        let index = self.cdf.partition_point(|&value| value <= random);
+        index.saturating_sub(1).min(count - 1)
+    }
+}
+
+/// An equirectangular (latitude-longitude) HDR image wrapped around the
+/// scene, importance sampled by the brightness of its pixels. The middle
+/// of the image faces +z, with +x to its right and +y at the top
+pub struct EnvironmentMap {
+    image: Image,
+    rotation: f64,
+    intensity: f64,
+    rows: Distribution,
+    columns: Vec<Distribution>,
+}
+
+impl EnvironmentMap {
+    /// Creates a new environment map (from a non-empty image), spun around
+    /// +y by `rotation` degrees and with its colors multiplied by `intensity`
+    pub fn new(image: Image, rotation: f64, intensity: f64) -> EnvironmentMap {
+        let height = image.height() as usize;
+
+        // weigh pixels by how much of the sphere they cover, which shrinks towards the poles
+        let mut row_weights = Vec::with_capacity(height);
+        let mut columns = Vec::with_capacity(height);
+        for row in 0..height {
+            let sin_theta = (PI * (row as f64 + 0.5) / height as f64).sin();
+            let weights: Vec<f64> = image
+                .row(row as u32)
+                .iter()
+                .map(|color| color.luminance().max(0.0) * sin_theta)
+                .collect();
+
+            row_weights.push(weights.iter().sum());
+            columns.push(Distribution::new(&weights));
+        }
+
+        EnvironmentMap {
+            image,
+            rotation: rotation / 360.0,
+            intensity,
+            rows: Distribution::new(&row_weights),
+            columns,
+        }
+    }
+
+    /// Converts a direction into texture coordinates (from 0 to 1, with v = 0 at the top)
+    fn direction_to_uv(&self, direction: &Vector3) -> (f64, f64) {
+        let phi = direction.x().atan2(direction.z());
+        let u = (0.5 + phi / (2.0 * PI) - self.rotation).rem_euclid(1.0);
+        let v = direction.y().clamp(-1.0, 1.0).acos() / PI;
+        (u, v)
+    }
+
+    /// Converts texture coordinates into a direction
+    fn uv_to_direction(&self, u: f64, v: f64) -> Vector3 {
+        let phi = (u - 0.5 + self.rotation) * 2.0 * PI;
+        let theta = v * PI;
+        Vector3::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            theta.sin() * phi.cos(),
+        )
+    }
+
+    /// Returns the pixel containing some texture coordinates
+    fn pixel(&self, u: f64, v: f64) -> (usize, usize) {
+        let width = self.image.width() as usize;
+        let height = self.image.height() as usize;
+        (
+            ((u * width as f64) as usize).min(width - 1),
+            ((v * height as f64) as usize).min(height - 1),
+        )
+    }
+
+    /// Returns the probability density over directions of sampling a pixel
+    fn pixel_pdf(&self, x: usize, row: usize, v: f64) -> f64 {
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        // the chance of the pixel spread over its area in texture coordinates,
+        // then over the area of the sphere it covers
+        let pixel_count = self.image.width() as f64 * self.image.height() as f64;
+        let probability = self.rows.probability(row) * self.columns[row].probability(x);
+        probability * pixel_count / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, direction: &Vector3) -> Vector3 {
+        let (u, v) = self.direction_to_uv(direction);
+        let (x, row) = self.pixel(u, v);
+        self.image.get(x as u32, row as u32) * self.intensity
+    }
+
+    fn sample(&self, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        // pick a pixel and then a point inside of it
+        let (row_choice, column_choice) = sampler.get_2d();
+        let row = self.rows.sample(row_choice);
+        let x = self.columns[row].sample(column_choice);
+
+        let (u_offset, v_offset) = sampler.get_2d();
+        let u = (x as f64 + u_offset) / self.image.width() as f64;
+        let v = (row as f64 + v_offset) / self.image.height() as f64;
+
+        let pdf = self.pixel_pdf(x, row, v);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction: self.uv_to_direction(u, v),
+            distance: f64::INFINITY,
+            radiance: self.image.get(x as u32, row as u32) * self.intensity,
+            pdf,
+            can_be_hit: true,
+        })
+    }
+
+    fn pdf(&self, direction: &Vector3) -> f64 {
+        let (u, v) = self.direction_to_uv(direction);
+        let (x, row) = self.pixel(u, v);
+        self.pixel_pdf(x, row, v)
+    }
+}
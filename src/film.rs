@@ -0,0 +1,375 @@
+use crate::filter::Filter;
+use crate::image::Image;
+use crate::ray::PathStats;
+use crate::settings::RenderSettings;
+use crate::vector3::Vector3 as Color;
+use anyhow::{bail, Context};
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+// the first line of every checkpoint file
+const CHECKPOINT_SIGNATURE: &str = "RAY-TRACING-CHECKPOINT 1";
+
+// the size of a pixel in a checkpoint (a weighted sum of colors and its weight,
+// a sample count, and the mean and squared deviations of the luminance)
+const CHECKPOINT_PIXEL_BYTES: usize = 3 * 8 + 8 + 4 + 8 + 8;
+
+// keeps the noise of nearly black pixels from being divided by almost nothing
+const MIN_NOISE_LUMINANCE: f64 = 1e-4;
+
+/// The samples taken for a pixel, with a running mean and
+/// variance of their luminance (using Welford's algorithm)
+#[derive(Copy, Clone)]
+pub struct Pixel {
+    samples: u32,
+    mean: f64,
+    squared_deviations: f64,
+}
+
+impl Default for Pixel {
+    fn default() -> Pixel {
+        Pixel {
+            samples: 0,
+            mean: 0.0,
+            squared_deviations: 0.0,
+        }
+    }
+}
+
+impl Pixel {
+    /// Adds a sample to the pixel
+    pub fn add_sample(&mut self, color: Color) {
+        self.samples += 1;
+
+        let luminance = color.luminance();
+        let delta = luminance - self.mean;
+        self.mean += delta / self.samples as f64;
+        self.squared_deviations += delta * (luminance - self.mean);
+    }
+
+    /// Adds the samples of another pixel (Chan et al.'s parallel variance)
+    pub fn merge(&mut self, other: &Pixel) {
+        if other.samples == 0 {
+            return;
+        }
+
+        let samples = self.samples + other.samples;
+        let delta = other.mean - self.mean;
+        let other_fraction = other.samples as f64 / samples as f64;
+
+        self.squared_deviations +=
+            other.squared_deviations + delta * delta * self.samples as f64 * other_fraction;
+        self.mean += delta * other_fraction;
+        self.samples = samples;
+    }
+
+    /// Returns how noisy the pixel still is: the standard error of its luminance
+    /// divided by the square root of the luminance, which roughly follows how
+    /// visible the noise is once the sRGB curve has brightened dark pixels
+    pub fn noise(&self) -> f64 {
+        if self.samples < 2 {
+            return f64::INFINITY;
+        }
+
+        let variance = self.squared_deviations / (self.samples - 1) as f64;
+        let standard_error = (variance / self.samples as f64).sqrt();
+        standard_error / self.mean.max(MIN_NOISE_LUMINANCE).sqrt()
+    }
+}
+
+/// The samples that landed near a pixel, weighted by the reconstruction filter
+#[derive(Copy, Clone)]
+pub struct Splat {
+    sum: Color,
+    weight: f64,
+}
+
+impl Default for Splat {
+    fn default() -> Splat {
+        Splat {
+            sum: Color::default(),
+            weight: 0.0,
+        }
+    }
+}
+
+impl Splat {
+    /// Adds the splats of another pixel
+    fn merge(&mut self, other: &Splat) {
+        self.sum += other.sum;
+        self.weight += other.weight;
+    }
+
+    /// Returns the weighted average of the samples (black if there aren't any)
+    fn color(&self) -> Color {
+        if self.weight > 0.0 {
+            self.sum / self.weight
+        } else {
+            Color::default()
+        }
+    }
+}
+
+/// A rectangle of pixels (which can reach past the edges of the image)
+/// that samples are splatted onto, so each thread can splat on its own
+pub struct SplatRegion {
+    x: i64,
+    row: i64,
+    width: u32,
+    height: u32,
+    splats: Vec<Splat>,
+}
+
+impl SplatRegion {
+    /// Creates a new empty region with its top left pixel at `x`, `row`
+    pub fn new(x: i64, row: i64, width: u32, height: u32) -> SplatRegion {
+        SplatRegion {
+            x,
+            row,
+            width,
+            height,
+            splats: vec![Splat::default(); width as usize * height as usize],
+        }
+    }
+
+    /// Adds a sample taken at a point on the image (in pixels from the top left
+    /// corner) to every pixel in the region whose center the filter reaches
+    pub fn splat(&mut self, filter: &Filter, x: f64, y: f64, color: Color) {
+        // the region's pixels whose centers are within the radius
+        let radius = filter.radius();
+        let first_x = ((x - 0.5 - radius).ceil() as i64).max(self.x);
+        let last_x = ((x - 0.5 + radius).floor() as i64).min(self.x + self.width as i64 - 1);
+        let first_row = ((y - 0.5 - radius).ceil() as i64).max(self.row);
+        let last_row = ((y - 0.5 + radius).floor() as i64).min(self.row + self.height as i64 - 1);
+
+        for row in first_row..=last_row {
+            let row_weight = filter.evaluate(row as f64 + 0.5 - y);
+            if row_weight == 0.0 {
+                continue;
+            }
+
+            for pixel_x in first_x..=last_x {
+                let weight = row_weight * filter.evaluate(pixel_x as f64 + 0.5 - x);
+                let index =
+                    (row - self.row) as usize * self.width as usize + (pixel_x - self.x) as usize;
+
+                let splat = &mut self.splats[index];
+                splat.sum += color * weight;
+                splat.weight += weight;
+            }
+        }
+    }
+}
+
+/// Accumulates the samples of a progressive render, so more
+/// passes can keep being added on top
+pub struct Film {
+    width: u32,
+    height: u32,
+    pixels: Vec<Pixel>,
+    splats: Vec<Splat>,
+}
+
+impl Film {
+    /// Creates a new film without any samples
+    pub fn new(width: u32, height: u32) -> Film {
+        Film {
+            width,
+            height,
+            pixels: vec![Pixel::default(); width as usize * height as usize],
+            splats: vec![Splat::default(); width as usize * height as usize],
+        }
+    }
+
+    /// Returns the fewest samples any pixel has
+    pub fn fewest_samples(&self) -> u32 {
+        self.pixels
+            .iter()
+            .map(|pixel| pixel.samples)
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Decides which samples each pixel takes in the next pass (by their index
+    /// among the pixel's samples), row by row from the top of the image. Pixels
+    /// stop once they reach `rays_per_pixel` samples, or once they have at least
+    /// `min_rays_per_pixel` and are less noisy than the noise threshold (an empty
+    /// range if the pixel is finished)
+    pub fn next_pass(&self, settings: &RenderSettings) -> Vec<Range<u32>> {
+        let min_samples = settings.min_rays_per_pixel;
+
+        self.pixels
+            .iter()
+            .map(|pixel| {
+                let converged =
+                    pixel.samples >= min_samples && pixel.noise() < settings.noise_threshold;
+
+                let count = if converged {
+                    0
+                } else {
+                    settings
+                        .samples_per_pass
+                        .min(settings.rays_per_pixel.saturating_sub(pixel.samples))
+                };
+                pixel.samples..pixel.samples + count
+            })
+            .collect()
+    }
+
+    /// Adds a pass's pixels (row by row from the top of the image) and the regions
+    /// its samples were splatted onto (added in order, so the sums come out the
+    /// same no matter which threads rendered what)
+    pub fn add_pass(&mut self, pass: &[Pixel], regions: &[SplatRegion]) {
+        for (pixel, samples) in self.pixels.iter_mut().zip(pass) {
+            pixel.merge(samples);
+        }
+
+        for region in regions {
+            for (index, splat) in region.splats.iter().enumerate() {
+                let x = region.x + (index % region.width as usize) as i64;
+                let row = region.row + (index / region.width as usize) as i64;
+
+                // splats past the edges of the image are dropped
+                if (0..self.width as i64).contains(&x) && (0..self.height as i64).contains(&row) {
+                    self.splats[row as usize * self.width as usize + x as usize].merge(splat);
+                }
+            }
+        }
+    }
+
+    /// Returns the filtered average of every sample taken so far
+    pub fn image(&self) -> Image {
+        let mut image = Image::new(self.width, self.height);
+        for (color, splat) in image.pixels_mut().iter_mut().zip(&self.splats) {
+            *color = splat.color();
+        }
+        image
+    }
+
+    /// Returns an image of how many samples each pixel took compared to
+    /// `max_samples`, going from blue (few) through green to red (many)
+    pub fn sample_heatmap(&self, max_samples: u32) -> Image {
+        let mut image = Image::new(self.width, self.height);
+        for (color, pixel) in image.pixels_mut().iter_mut().zip(&self.pixels) {
+            let amount = (pixel.samples as f64 / max_samples.max(1) as f64).clamp(0.0, 1.0);
+            *color = if amount < 0.5 {
+                Color::new(0.0, 0.0, 1.0).lerp(&Color::new(0.0, 1.0, 0.0), amount * 2.0)
+            } else {
+                Color::new(0.0, 1.0, 0.0).lerp(&Color::new(1.0, 0.0, 0.0), amount * 2.0 - 1.0)
+            };
+        }
+        image
+    }
+
+    /// Saves the film and the stats of its paths so the render can be resumed, tagged
+    /// with a fingerprint of the scene (the file is replaced in one step, so a render
+    /// killed while saving still leaves the previous checkpoint)
+    pub fn save_checkpoint(
+        &self,
+        path: &Path,
+        fingerprint: u64,
+        stats: &PathStats,
+    ) -> anyhow::Result<()> {
+        let header = format!(
+            "{}\n{} {} {} {} {}\n",
+            CHECKPOINT_SIGNATURE, fingerprint, self.width, self.height, stats.paths, stats.bounces
+        );
+
+        let mut bytes = header.into_bytes();
+        for (pixel, splat) in self.pixels.iter().zip(&self.splats) {
+            for channel in 0..3 {
+                bytes.extend_from_slice(&splat.sum[channel].to_le_bytes());
+            }
+            bytes.extend_from_slice(&splat.weight.to_le_bytes());
+            bytes.extend_from_slice(&pixel.samples.to_le_bytes());
+            bytes.extend_from_slice(&pixel.mean.to_le_bytes());
+            bytes.extend_from_slice(&pixel.squared_deviations.to_le_bytes());
+        }
+
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        fs::write(&temporary, bytes)
+            .and_then(|_| fs::rename(&temporary, path))
+            .with_context(|| format!("Issue writing checkpoint `{}`", path.display()))
+    }
+
+    /// Loads a film and its path stats from a checkpoint, making sure it was
+    /// saved for a scene with the same fingerprint and image size
+    pub fn load_checkpoint(
+        path: &Path,
+        fingerprint: u64,
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<(Film, PathStats)> {
+        let bytes = fs::read(path)
+            .with_context(|| format!("Issue reading checkpoint `{}`", path.display()))?;
+
+        Film::decode_checkpoint(&bytes, fingerprint, width, height)
+            .with_context(|| format!("Issue loading checkpoint `{}`", path.display()))
+    }
+
+    /// Decodes the contents of a checkpoint file
+    fn decode_checkpoint(
+        bytes: &[u8],
+        fingerprint: u64,
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<(Film, PathStats)> {
+        // the header is two lines of text
+        let mut lines = bytes.splitn(3, |&byte| byte == b'\n');
+        let signature = lines.next().unwrap_or_default();
+        let numbers = lines.next().unwrap_or_default();
+        let data = lines.next().unwrap_or_default();
+
+        if signature != CHECKPOINT_SIGNATURE.as_bytes() {
+            bail!("Not a checkpoint file (or one from an older version)");
+        }
+
+        let numbers = std::str::from_utf8(numbers).ok().and_then(|numbers| {
+            numbers
+                .split_whitespace()
+                .map(|number| number.parse::<u64>().ok())
+                .collect::<Option<Vec<u64>>>()
+        });
+        let [saved_fingerprint, saved_width, saved_height, paths, bounces] =
+            match numbers.as_deref() {
+                Some(&[a, b, c, d, e]) => [a, b, c, d, e],
+                _ => bail!("The header is invalid"),
+            };
+
+        if saved_fingerprint != fingerprint {
+            bail!("The checkpoint was saved for a different scene or settings");
+        }
+        if (saved_width, saved_height) != (width as u64, height as u64) {
+            bail!(
+                "The checkpoint is {}x{} pixels but the image is {}x{}",
+                saved_width,
+                saved_height,
+                width,
+                height
+            );
+        }
+
+        let mut film = Film::new(width, height);
+        if data.len() != film.pixels.len() * CHECKPOINT_PIXEL_BYTES {
+            bail!("The pixel data is the wrong size");
+        }
+
+        let f64_at = |chunk: &[u8], offset: usize| {
+            let mut value = [0; 8];
+            value.copy_from_slice(&chunk[offset..offset + 8]);
+            f64::from_le_bytes(value)
+        };
+        let chunks = data.chunks_exact(CHECKPOINT_PIXEL_BYTES);
+        for ((pixel, splat), chunk) in film.pixels.iter_mut().zip(&mut film.splats).zip(chunks) {
+            splat.sum = Color::new(f64_at(chunk, 0), f64_at(chunk, 8), f64_at(chunk, 16));
+            splat.weight = f64_at(chunk, 24);
+            pixel.samples = u32::from_le_bytes([chunk[32], chunk[33], chunk[34], chunk[35]]);
+            pixel.mean = f64_at(chunk, 36);
+            pixel.squared_deviations = f64_at(chunk, 44);
+        }
+
+        Ok((film, PathStats { paths, bounces }))
+    }
+}
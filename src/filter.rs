@@ -0,0 +1,103 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+// samples are only placed inside their own pixel, so a smaller radius
+// would leave some pixel centers out of reach of every sample
+pub const MIN_FILTER_RADIUS: f64 = 0.5;
+
+// the largest radius allowed, which keeps the regions every tile
+// splats onto (held in memory for a whole pass) from growing too large
+pub const MAX_FILTER_RADIUS: f64 = 8.0;
+
+/// The shapes of filter that can turn samples into pixels
+#[derive(Copy, Clone, Default, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterKind {
+    /// Weighs every sample within the radius the same
+    Box,
+    /// Weighs samples less the further they are from the pixel, in a straight line
+    Tent,
+    /// A Gaussian bell curve that reaches 0 at the radius
+    #[default]
+    Gaussian,
+    /// The Mitchell-Netravali cubic (B = C = 1/3), which is sharper but can ring
+    Mitchell,
+    /// The Blackman-Harris window, which is smooth with almost no ringing
+    BlackmanHarris,
+}
+
+impl FilterKind {
+    /// Returns the radius (in pixels) the filter usually has
+    pub fn default_radius(self) -> f64 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::BlackmanHarris => 2.0,
+        }
+    }
+}
+
+/// A reconstruction filter, which decides how much a sample adds to
+/// each pixel around it based on how far away the pixel's center is
+/// (the filter is separable, so it's the product of the weights along
+/// each axis)
+#[derive(Copy, Clone)]
+pub struct Filter {
+    kind: FilterKind,
+    radius: f64,
+}
+
+impl Filter {
+    /// Creates a new filter that reaches `radius` pixels out from a sample
+    pub fn new(kind: FilterKind, radius: f64) -> Filter {
+        Filter { kind, radius }
+    }
+
+    /// Returns how far (in pixels) the filter reaches from a sample
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    /// Returns the weight along one axis for a pixel `offset` pixels away from a sample
+    pub fn evaluate(&self, offset: f64) -> f64 {
+        let distance = offset.abs();
+        if distance > self.radius {
+            return 0.0;
+        }
+
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => self.radius - distance,
+            FilterKind::Gaussian => {
+                // subtract the value at the radius so the curve ends at 0
+                let sigma = self.radius / 3.0;
+                let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(distance) - gaussian(self.radius)).max(0.0)
+            }
+            FilterKind::Mitchell => {
+                // the cubic is defined from 0 to 2, so stretch it over the radius
+                let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+                let x = 2.0 * distance / self.radius;
+                let weight = if x < 1.0 {
+                    (12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b)
+                } else {
+                    (-b - 6.0 * c) * x * x * x
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c)
+                };
+                weight / 6.0
+            }
+            FilterKind::BlackmanHarris => {
+                // the window goes from 0 at one side of the radius to the other
+                let t = 2.0 * PI * (offset + self.radius) / (2.0 * self.radius);
+                0.35875 - 0.48829 * t.cos() + 0.14128 * (2.0 * t).cos() - 0.01168 * (3.0 * t).cos()
+            }
+        }
+    }
+}
@@ -1,26 +1,29 @@
 use crate::material::Material;
 use crate::vector3::Vector3;
 
+// hits closer than this are ignored so rays leaving a surface don't hit it again
+pub const HIT_EPSILON: f64 = 1e-8;
+
 /// Represents a hit of an object by a ray
 /// Note: the normal will always face outwards
 #[derive(Copy, Clone)]
-pub struct Hit {
+pub struct Hit<'a> {
     pub distance: f64,
     pub point: Vector3,
     pub normal: Vector3,
     pub outside_face: bool,
-    pub material: Material,
+    pub material: &'a dyn Material,
 }
 
-impl Hit {
+impl<'a> Hit<'a> {
     /// Creates a new Hit from a distance, point, and normal
     pub fn new(
         distance: f64,
         point: Vector3,
         normal: Vector3,
         outside_face: bool,
-        material: Material,
-    ) -> Hit {
+        material: &'a dyn Material,
+    ) -> Hit<'a> {
         Hit {
             distance,
             point,
@@ -29,4 +32,13 @@ impl Hit {
             material,
         }
     }
+
+    /// Returns the normal flipped to face against the ray that hit
+    pub fn facing_normal(&self) -> Vector3 {
+        if self.outside_face {
+            self.normal
+        } else {
+            -self.normal
+        }
+    }
 }
@@ -0,0 +1,63 @@
+use crate::vector3::Vector3 as Color;
+
+/// A rendered image stored as unclamped floating point colors,
+/// with rows going from the top of the image to the bottom
+#[derive(Clone)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
+}
+
+impl Image {
+    /// Creates a new black image
+    pub fn new(width: u32, height: u32) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![Color::default(); width as usize * height as usize],
+        }
+    }
+
+    /// Returns the width of the image in pixels
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the image in pixels
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Sets the color of a pixel (row 0 is the top of the image)
+    pub fn set(&mut self, x: u32, row: u32, color: Color) {
+        let index = self.index(x, row);
+        self.pixels[index] = color;
+    }
+
+    /// Returns the color of a pixel (row 0 is the top of the image)
+    pub fn get(&self, x: u32, row: u32) -> Color {
+        self.pixels[self.index(x, row)]
+    }
+
+    /// Returns the pixels of a row (row 0 is the top of the image)
+    pub fn row(&self, row: u32) -> &[Color] {
+        let start = self.index(0, row);
+        &self.pixels[start..start + self.width as usize]
+    }
+
+    /// Returns every pixel, row by row from the top of the image
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    /// Returns every pixel mutably, row by row from the top of the image
+    pub fn pixels_mut(&mut self) -> &mut [Color] {
+        &mut self.pixels
+    }
+
+    /// Gets the index of a pixel in the pixel buffer
+    fn index(&self, x: u32, row: u32) -> usize {
+        row as usize * self.width as usize + x as usize
+    }
+}
@@ -0,0 +1,446 @@
+use crate::image::Image;
+use crate::vector3::Vector3 as Color;
+use anyhow::{bail, Context};
+use std::fs;
+use std::path::Path;
+
+// the most pixels a byte of Radiance pixel data can hold, since run-length
+// encoded scanlines take at least 2 bytes for every 127 pixels of each of
+// their 4 channels (old-style repeats can hold more, but are rarely used)
+const RADIANCE_MAX_PIXELS_PER_BYTE: u64 = 16;
+
+// how far old-style repeat counts can be shifted by consecutive repeat
+// pixels (each one holds the next 8 bits of the count, up to 32 bits)
+const RADIANCE_MAX_REPEAT_SHIFT: usize = 24;
+
+/// Reads a floating point image from a Radiance `.hdr` or `.pfm` file
+pub fn load_hdr_image(path: &Path) -> anyhow::Result<Image> {
+    let bytes =
+        fs::read(path).with_context(|| format!("Issue reading image `{}`", path.display()))?;
+
+    let image = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("hdr") => decode_radiance(&bytes),
+        Some("pfm") => decode_pfm(&bytes),
+        _ => bail!(
+            "Image `{}` must have a `.hdr` or `.pfm` extension",
+            path.display()
+        ),
+    };
+
+    image.with_context(|| format!("Issue decoding image `{}`", path.display()))
+}
+
+/// Splits the next line (without its newline) off of the start of some bytes
+fn read_line<'a>(bytes: &mut &'a [u8]) -> anyhow::Result<&'a str> {
+    let end = match bytes.iter().position(|&byte| byte == b'\n') {
+        Some(end) => end,
+        None => bail!("The header ended early"),
+    };
+
+    let line = std::str::from_utf8(&bytes[..end]).with_context(|| "The header isn't text")?;
+    *bytes = &bytes[end + 1..];
+
+    Ok(line.trim_end_matches('\r'))
+}
+
+/// Parses a whitespace separated number from a header
+fn parse_header_number<T: std::str::FromStr>(token: Option<&str>) -> anyhow::Result<T> {
+    match token.and_then(|token| token.parse().ok()) {
+        Some(number) => Ok(number),
+        None => bail!("The header has a missing or invalid number"),
+    }
+}
+
+/// Converts a shared-exponent RGBE pixel into a color
+fn rgbe_to_color([red, green, blue, exponent]: [u8; 4]) -> Color {
+    if exponent == 0 {
+        return Color::default();
+    }
+
+    // the mantissas were truncated, so use the middle of their range
+    let scale = 2f64.powi(exponent as i32 - (128 + 8));
+    Color::new(
+        (red as f64 + 0.5) * scale,
+        (green as f64 + 0.5) * scale,
+        (blue as f64 + 0.5) * scale,
+    )
+}
+
+/// Decodes a Radiance RGBE image with flat, run-length encoded or old-style
+/// run-length encoded scanlines (only the standard `-Y h +X w` orientation)
+fn decode_radiance(mut bytes: &[u8]) -> anyhow::Result<Image> {
+    if !read_line(&mut bytes)?.starts_with("#?") {
+        bail!("Missing the `#?RADIANCE` signature");
+    }
+
+    // header variables end with an empty line
+    loop {
+        let line = read_line(&mut bytes)?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                bail!("Unsupported pixel format `{}`", format);
+            }
+        }
+    }
+
+    let resolution = read_line(&mut bytes)?;
+    let mut tokens = resolution.split_whitespace();
+    if tokens.next() != Some("-Y") {
+        bail!("Unsupported orientation `{}`", resolution);
+    }
+    let height: u32 = parse_header_number(tokens.next())?;
+    if tokens.next() != Some("+X") {
+        bail!("Unsupported orientation `{}`", resolution);
+    }
+    let width: u32 = parse_header_number(tokens.next())?;
+    if width == 0 || height == 0 {
+        bail!("The image is empty ({}x{})", width, height);
+    }
+
+    // check the file could hold that many pixels before making room for them
+    let pixel_count = width as u64 * height as u64;
+    if pixel_count > bytes.len() as u64 * RADIANCE_MAX_PIXELS_PER_BYTE {
+        bail!(
+            "The header says the image is {}x{}, but there are only {} bytes of pixel data",
+            width,
+            height,
+            bytes.len()
+        );
+    }
+
+    let mut image = Image::new(width, height);
+    let mut scanline = vec![[0u8; 4]; width as usize];
+
+    let mut take = |count: usize| -> anyhow::Result<&[u8]> {
+        if bytes.len() < count {
+            bail!("The pixel data ended early");
+        }
+        let (taken, rest) = bytes.split_at(count);
+        bytes = rest;
+        Ok(taken)
+    };
+
+    for row in 0..height {
+        let start = take(4)?;
+        let is_rle = (8..0x8000).contains(&width)
+            && start[0] == 2
+            && start[1] == 2
+            && (((start[2] as u32) << 8) | start[3] as u32) == width;
+
+        if is_rle {
+            // each channel is stored separately as runs and literal spans
+            for channel in 0..4 {
+                let mut x = 0;
+                while x < width as usize {
+                    let count = take(1)?[0] as usize;
+                    let (length, run) = if count > 128 {
+                        (count - 128, true)
+                    } else {
+                        (count, false)
+                    };
+                    if length == 0 || x + length > width as usize {
+                        bail!("Invalid run length on row {}", row);
+                    }
+
+                    if run {
+                        let value = take(1)?[0];
+                        scanline[x..x + length]
+                            .iter_mut()
+                            .for_each(|pixel| pixel[channel] = value);
+                    } else {
+                        for (pixel, &value) in scanline[x..x + length].iter_mut().zip(take(length)?)
+                        {
+                            pixel[channel] = value;
+                        }
+                    }
+                    x += length;
+                }
+            }
+        } else {
+            // flat pixels, where (1, 1, 1, n) repeats the previous pixel
+            let mut x = 0;
+            let mut shift = 0;
+            let mut pixel = [start[0], start[1], start[2], start[3]];
+            loop {
+                if pixel[..3] == [1, 1, 1] && x > 0 {
+                    if shift > RADIANCE_MAX_REPEAT_SHIFT {
+                        bail!("Too many repeat pixels in a row on row {}", row);
+                    }
+                    let repeat = (pixel[3] as usize) << shift;
+                    if x + repeat > width as usize {
+                        bail!("Invalid run length on row {}", row);
+                    }
+                    let previous = scanline[x - 1];
+                    scanline[x..x + repeat].fill(previous);
+                    x += repeat;
+                    shift += 8;
+                } else {
+                    scanline[x] = pixel;
+                    x += 1;
+                    shift = 0;
+                }
+
+                if x >= width as usize {
+                    break;
+                }
+                let next = take(4)?;
+                pixel = [next[0], next[1], next[2], next[3]];
+            }
+        }
+
+        for (x, &pixel) in scanline.iter().enumerate() {
+            image.set(x as u32, row, rgbe_to_color(pixel));
+        }
+    }
+
+    Ok(image)
+}
+
+/// Decodes a color (`PF`) or greyscale (`Pf`) portable float map
+fn decode_pfm(mut bytes: &[u8]) -> anyhow::Result<Image> {
+    let channels = match read_line(&mut bytes)? {
+        "PF" => 3,
+        "Pf" => 1,
+        signature => bail!("Unknown PFM signature `{}`", signature),
+    };
+
+    let dimensions = read_line(&mut bytes)?;
+    let mut tokens = dimensions.split_whitespace();
+    let width: u32 = parse_header_number(tokens.next())?;
+    let height: u32 = parse_header_number(tokens.next())?;
+    if width == 0 || height == 0 {
+        bail!("The image is empty ({}x{})", width, height);
+    }
+
+    // a negative scale means the data is little-endian
+    let scale: f64 = parse_header_number(Some(read_line(&mut bytes)?.trim()))?;
+    let little_endian = scale < 0.0;
+
+    let expected = width as usize * height as usize * channels * 4;
+    if bytes.len() < expected {
+        bail!(
+            "Expected {} bytes of pixel data but found {}",
+            expected,
+            bytes.len()
+        );
+    }
+
+    let mut values = bytes[..expected].chunks_exact(4).map(|chunk| {
+        let chunk = [chunk[0], chunk[1], chunk[2], chunk[3]];
+        if little_endian {
+            f32::from_le_bytes(chunk) as f64
+        } else {
+            f32::from_be_bytes(chunk) as f64
+        }
+    });
+
+    // PFM rows go from the bottom of the image to the top
+    let mut image = Image::new(width, height);
+    for row in (0..height).rev() {
+        for x in 0..width {
+            let color = match channels {
+                3 => Color::new(
+                    values.next().unwrap_or(0.0),
+                    values.next().unwrap_or(0.0),
+                    values.next().unwrap_or(0.0),
+                ),
+                _ => {
+                    let value = values.next().unwrap_or(0.0);
+                    Color::new(value, value, value)
+                }
+            };
+            image.set(x, row, color);
+        }
+    }
+
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{Encoder, HdrEncoder, PfmEncoder};
+
+    const RADIANCE_HEADER: &[u8] = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n";
+
+    /// Makes an image with a spread of colors (and some runs of the same color)
+    fn test_image(width: u32, height: u32) -> Image {
+        let mut image = Image::new(width, height);
+        for row in 0..height {
+            for x in 0..width {
+                let color = match x % 5 {
+                    0 | 1 => Color::new(0.25, 0.5, 0.75),
+                    _ => Color::new(
+                        x as f64 * 0.37 + 0.01,
+                        row as f64 * 2.5,
+                        (x * row) as f64 * 0.05 + 0.001,
+                    ),
+                };
+                image.set(x, row, color);
+            }
+        }
+        image
+    }
+
+    /// Encodes an image into the bytes of a file
+    fn encode(encoder: &dyn Encoder, image: &Image) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        encoder.encode(image, &mut bytes).unwrap();
+        bytes
+    }
+
+    /// Checks that a decoded HDR image matches the original up to the
+    /// precision of the RGBE format (8 bits below the brightest channel)
+    fn assert_rgbe_close(decoded: &Image, original: &Image) {
+        assert_eq!(decoded.width(), original.width());
+        assert_eq!(decoded.height(), original.height());
+
+        for (decoded, original) in decoded.pixels().iter().zip(original.pixels()) {
+            let brightest = original[0].max(original[1]).max(original[2]);
+            for channel in 0..3 {
+                assert!((decoded[channel] - original[channel]).abs() <= brightest / 128.0);
+            }
+        }
+    }
+
+    /// Rewrites the flat scanlines of an HDR file from `HdrEncoder` as
+    /// run-length encoded scanlines, using runs wherever a value repeats
+    fn to_run_length_encoded(bytes: &[u8], width: usize) -> Vec<u8> {
+        let header_end = bytes.windows(2).rposition(|pair| pair == b"X ").unwrap();
+        let data_start = header_end
+            + bytes[header_end..]
+                .iter()
+                .position(|&b| b == b'\n')
+                .unwrap()
+            + 1;
+
+        let mut encoded = bytes[..data_start].to_vec();
+        for scanline in bytes[data_start..].chunks(width * 4) {
+            encoded.extend_from_slice(&[2, 2, (width >> 8) as u8, width as u8]);
+
+            for channel in 0..4 {
+                let values: Vec<u8> = scanline.chunks(4).map(|pixel| pixel[channel]).collect();
+                let mut x = 0;
+                while x < width {
+                    let run = values[x..]
+                        .iter()
+                        .take_while(|&&value| value == values[x])
+                        .count();
+                    if run >= 2 {
+                        let run = run.min(127);
+                        encoded.extend_from_slice(&[128 + run as u8, values[x]]);
+                        x += run;
+                    } else {
+                        encoded.extend_from_slice(&[1, values[x]]);
+                        x += 1;
+                    }
+                }
+            }
+        }
+        encoded
+    }
+
+    #[test]
+    fn flat_radiance_images_round_trip() {
+        let image = test_image(5, 3);
+        let decoded = decode_radiance(&encode(&HdrEncoder, &image)).unwrap();
+        assert_rgbe_close(&decoded, &image);
+    }
+
+    #[test]
+    fn run_length_encoded_radiance_images_round_trip() {
+        let image = test_image(20, 4);
+        let bytes = to_run_length_encoded(&encode(&HdrEncoder, &image), 20);
+        let decoded = decode_radiance(&bytes).unwrap();
+        assert_rgbe_close(&decoded, &image);
+    }
+
+    #[test]
+    fn old_style_repeats_are_decoded() {
+        // 300 pixels as 20 flat pixels, with the last one repeated 24 + (1 << 8) more times
+        let mut bytes = RADIANCE_HEADER.to_vec();
+        bytes.extend_from_slice(b"-Y 1 +X 300\n");
+        for x in 0..20 {
+            bytes.extend_from_slice(&[128, 64, x, 129]);
+        }
+        bytes.extend_from_slice(&[1, 1, 1, 24, 1, 1, 1, 1]);
+
+        let image = decode_radiance(&bytes).unwrap();
+        for x in 0..300 {
+            let expected = rgbe_to_color([128, 64, x.min(19) as u8, 129]);
+            assert_eq!((image.get(x, 0) - expected).length(), 0.0);
+        }
+    }
+
+    #[test]
+    fn endless_old_style_repeats_are_rejected() {
+        let mut bytes = RADIANCE_HEADER.to_vec();
+        bytes.extend_from_slice(b"-Y 1 +X 2\n");
+        bytes.extend_from_slice(&[128, 64, 32, 129]);
+        for _ in 0..9 {
+            bytes.extend_from_slice(&[1, 1, 1, 0]);
+        }
+
+        assert!(decode_radiance(&bytes).is_err());
+    }
+
+    #[test]
+    fn radiance_images_larger_than_their_data_are_rejected() {
+        let mut bytes = RADIANCE_HEADER.to_vec();
+        bytes.extend_from_slice(b"-Y 100000 +X 100000\n\0\0\0\0");
+        assert!(decode_radiance(&bytes).is_err());
+    }
+
+    #[test]
+    fn empty_radiance_images_are_rejected() {
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 0\n\0\0\0\0";
+        assert!(decode_radiance(header).is_err());
+
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 0 +X 2\n\0\0\0\0";
+        assert!(decode_radiance(header).is_err());
+    }
+
+    #[test]
+    fn pfm_images_round_trip() {
+        let image = test_image(9, 4);
+        let decoded = decode_pfm(&encode(&PfmEncoder, &image)).unwrap();
+
+        for (decoded, original) in decoded.pixels().iter().zip(image.pixels()) {
+            for channel in 0..3 {
+                assert_eq!(decoded[channel], original[channel] as f32 as f64);
+            }
+        }
+    }
+
+    #[test]
+    fn pfm_rows_go_from_bottom_to_top() {
+        let mut image = Image::new(1, 2);
+        image.set(0, 0, Color::new(1.0, 0.0, 0.0));
+        image.set(0, 1, Color::new(0.0, 0.0, 1.0));
+
+        // the first pixel in the file is the bottom (blue) one
+        let bytes = encode(&PfmEncoder, &image);
+        let data = &bytes[bytes.len() - 24..];
+        assert_eq!(data[..4], 0.0f32.to_le_bytes());
+        assert_eq!(data[8..12], 1.0f32.to_le_bytes());
+
+        let decoded = decode_pfm(&bytes).unwrap();
+        assert_eq!(
+            (decoded.get(0, 0) - Color::new(1.0, 0.0, 0.0)).length(),
+            0.0
+        );
+        assert_eq!(
+            (decoded.get(0, 1) - Color::new(0.0, 0.0, 1.0)).length(),
+            0.0
+        );
+    }
+
+    #[test]
+    fn empty_pfm_images_are_rejected() {
+        assert!(decode_pfm(b"PF\n0 2\n-1.0\n").is_err());
+        assert!(decode_pfm(b"Pf\n2 0\n-1.0\n").is_err());
+    }
+}
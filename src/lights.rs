@@ -0,0 +1,556 @@
+use crate::aabb::Aabb;
+use crate::hit::{Hit, HIT_EPSILON};
+use crate::material::{Emissive, Material};
+use crate::objects::Object;
+use crate::random::random_in_unit_disk;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vector3::Vector3;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// A direction towards a light chosen for next event estimation
+pub struct LightSample {
+    pub direction: Vector3,
+
+    /// How far along `direction` the light is (anything closer blocks it)
+    pub distance: f64,
+
+    /// The light arriving from the light along `direction`
+    pub radiance: Vector3,
+
+    /// The probability density (over solid angle) of choosing the direction
+    /// (for lights at a single point or direction, the chance of choosing it)
+    pub pdf: f64,
+
+    /// If a bounce could also find the light, so the two should be weighed
+    /// against each other (false for point-like lights and hidden lights)
+    pub can_be_hit: bool,
+}
+
+/// Represents something in a scene that light can be sampled from directly
+pub trait Light: Send + Sync {
+    /// Chooses a direction from `point` towards the light (or None if
+    /// the light can't be sampled from there)
+    fn sample(&self, point: &Vector3, sampler: &mut dyn Sampler) -> Option<LightSample>;
+
+    /// Returns the probability density of `sample` choosing the direction from
+    /// `origin` to a hit, or 0 if the hit isn't on this light
+    fn pdf(&self, origin: &Vector3, hit: &Hit) -> f64;
+}
+
+/// An emissive sphere, sampled uniformly over the cone
+/// of directions it covers as seen from a point
+pub struct SphereLight {
+    center: Vector3,
+    radius: f64,
+    material: Arc<dyn Material>,
+}
+
+impl SphereLight {
+    /// Creates a new sphere light from a sphere with an emissive material
+    pub fn new(center: Vector3, radius: f64, material: Arc<dyn Material>) -> SphereLight {
+        SphereLight {
+            center,
+            radius,
+            material,
+        }
+    }
+
+    /// Returns 1 minus the cosine of the half angle of the cone the sphere covers
+    /// as seen from a point, or None if the point is inside the sphere
+    fn cone_size(&self, point: &Vector3) -> Option<f64> {
+        let distance_squared = (self.center - *point).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return None;
+        }
+
+        // written this way so it doesn't round to 0 for small or distant spheres
+        let sin_squared = radius_squared / distance_squared;
+        let cos_max = (1.0 - sin_squared).sqrt();
+        Some(sin_squared / (1.0 + cos_max))
+    }
+}
+
+impl Light for SphereLight {
+    fn sample(&self, point: &Vector3, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        let cone_size = self.cone_size(point)?;
+
+        let to_center = self.center - *point;
+        let center_distance = to_center.length();
+        let axis = to_center / center_distance;
+
+        // pick a direction uniformly inside the cone
+        let (u1, u2) = sampler.get_2d();
+        let cos_theta = 1.0 - u1 * cone_size;
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+
+        let (tangent, bitangent) = axis.orthonormal_basis();
+        let direction = tangent * (sin_theta * phi.cos())
+            + bitangent * (sin_theta * phi.sin())
+            + axis * cos_theta;
+
+        // find where the direction hits the sphere
+        let projected = center_distance * cos_theta;
+        let offset_squared = center_distance * center_distance * sin_theta * sin_theta;
+        let distance = projected - (self.radius * self.radius - offset_squared).max(0.0).sqrt();
+
+        let point_on_light = *point + direction * distance;
+        let normal = (point_on_light - self.center) / self.radius;
+        let hit = Hit::new(
+            distance,
+            point_on_light,
+            normal,
+            true,
+            self.material.as_ref(),
+        );
+
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: self.material.emitted(&hit),
+            pdf: 1.0 / (2.0 * PI * cone_size),
+            can_be_hit: true,
+        })
+    }
+
+    fn pdf(&self, origin: &Vector3, hit: &Hit) -> f64 {
+        // make sure the hit is actually on this sphere
+        let surface_distance = (hit.point - self.center).length() - self.radius;
+        if surface_distance.abs() > 1e-6 * self.radius.max(1.0) {
+            return 0.0;
+        }
+
+        match self.cone_size(origin) {
+            Some(cone_size) => 1.0 / (2.0 * PI * cone_size),
+            None => 0.0,
+        }
+    }
+}
+
+/// Returns the light a point-like light sends to `point` from
+/// `position`, falling off with the square of the distance
+fn point_light_sample(
+    position: &Vector3,
+    point: &Vector3,
+    intensity: Vector3,
+) -> Option<LightSample> {
+    let offset = *position - *point;
+    let distance_squared = offset.length_squared();
+    if distance_squared == 0.0 {
+        return None;
+    }
+    let distance = distance_squared.sqrt();
+
+    Some(LightSample {
+        direction: offset / distance,
+        distance,
+        radiance: intensity / distance_squared,
+        pdf: 1.0,
+        can_be_hit: false,
+    })
+}
+
+/// A light giving off the same amount of light in every direction from a single point
+pub struct PointLight {
+    position: Vector3,
+    intensity: Vector3,
+}
+
+impl PointLight {
+    /// Creates a new point light from its position and color times intensity
+    pub fn new(position: Vector3, intensity: Vector3) -> PointLight {
+        PointLight {
+            position,
+            intensity,
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, point: &Vector3, _sampler: &mut dyn Sampler) -> Option<LightSample> {
+        point_light_sample(&self.position, point, self.intensity)
+    }
+
+    fn pdf(&self, _origin: &Vector3, _hit: &Hit) -> f64 {
+        0.0
+    }
+}
+
+/// A point light that only shines inside a cone, fading out between
+/// the inner and outer angles of the cone
+pub struct SpotLight {
+    position: Vector3,
+    direction: Vector3,
+    intensity: Vector3,
+    cos_inner: f64,
+    cos_outer: f64,
+}
+
+impl SpotLight {
+    /// Creates a new spot light shining along `direction`, with the
+    /// half angles (in degrees) of the cone's fully lit inside and edge
+    pub fn new(
+        position: Vector3,
+        direction: Vector3,
+        intensity: Vector3,
+        inner_angle: f64,
+        outer_angle: f64,
+    ) -> SpotLight {
+        SpotLight {
+            position,
+            direction: direction.normalized(),
+            intensity,
+            cos_inner: inner_angle.to_radians().cos(),
+            cos_outer: outer_angle.to_radians().cos(),
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, point: &Vector3, _sampler: &mut dyn Sampler) -> Option<LightSample> {
+        let mut sample = point_light_sample(&self.position, point, self.intensity)?;
+
+        // smoothly fade out from the inner angle to the outer angle
+        let cos_theta = (-sample.direction).dot(&self.direction);
+        if cos_theta <= self.cos_outer {
+            return None;
+        }
+        if cos_theta < self.cos_inner {
+            let t = (cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer);
+            sample.radiance *= t * t * (3.0 - 2.0 * t);
+        }
+
+        Some(sample)
+    }
+
+    fn pdf(&self, _origin: &Vector3, _hit: &Hit) -> f64 {
+        0.0
+    }
+}
+
+/// A light infinitely far away shining in a single direction, like the sun
+pub struct DirectionalLight {
+    direction: Vector3,
+    irradiance: Vector3,
+}
+
+impl DirectionalLight {
+    /// Creates a new directional light from the direction its
+    /// light travels in and its color times intensity
+    pub fn new(direction: Vector3, irradiance: Vector3) -> DirectionalLight {
+        DirectionalLight {
+            direction: direction.normalized(),
+            irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _point: &Vector3, _sampler: &mut dyn Sampler) -> Option<LightSample> {
+        Some(LightSample {
+            direction: -self.direction,
+            distance: f64::INFINITY,
+            radiance: self.irradiance,
+            pdf: 1.0,
+            can_be_hit: false,
+        })
+    }
+
+    fn pdf(&self, _origin: &Vector3, _hit: &Hit) -> f64 {
+        0.0
+    }
+}
+
+/// The flat shape of an area light
+#[derive(Copy, Clone)]
+pub enum AreaShape {
+    /// A parallelogram with a corner and the two edges leaving it
+    Rect {
+        corner: Vector3,
+        edge_1: Vector3,
+        edge_2: Vector3,
+    },
+
+    /// A disk with a center, normal and radius
+    Disk {
+        center: Vector3,
+        normal: Vector3,
+        radius: f64,
+    },
+}
+
+impl AreaShape {
+    /// Returns the side of the shape light comes out of (for rects,
+    /// this is the side edge_1 turns counter-clockwise to edge_2 on)
+    fn normal(&self) -> Vector3 {
+        match self {
+            AreaShape::Rect { edge_1, edge_2, .. } => edge_1.cross(edge_2).normalized(),
+            AreaShape::Disk { normal, .. } => normal.normalized(),
+        }
+    }
+
+    /// Returns the area of the shape
+    fn area(&self) -> f64 {
+        match self {
+            AreaShape::Rect { edge_1, edge_2, .. } => edge_1.cross(edge_2).length(),
+            AreaShape::Disk { radius, .. } => PI * radius * radius,
+        }
+    }
+
+    /// Returns a uniformly random point on the shape
+    fn sample_point(&self, sampler: &mut dyn Sampler) -> Vector3 {
+        match self {
+            AreaShape::Rect {
+                corner,
+                edge_1,
+                edge_2,
+            } => {
+                let (u1, u2) = sampler.get_2d();
+                *corner + *edge_1 * u1 + *edge_2 * u2
+            }
+            AreaShape::Disk { center, radius, .. } => {
+                let (tangent, bitangent) = self.normal().orthonormal_basis();
+                let disk = random_in_unit_disk(sampler) * *radius;
+                *center + tangent * disk.x() + bitangent * disk.y()
+            }
+        }
+    }
+
+    /// If a point on the shape's plane is inside the shape
+    fn contains(&self, point: &Vector3) -> bool {
+        match self {
+            AreaShape::Rect {
+                corner,
+                edge_1,
+                edge_2,
+            } => {
+                // solve for the point's position along both edges
+                let relative = *point - *corner;
+                let normal = edge_1.cross(edge_2);
+                let normal_squared = normal.length_squared();
+                let u = relative.cross(edge_2).dot(&normal) / normal_squared;
+                let v = edge_1.cross(&relative).dot(&normal) / normal_squared;
+                (0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&v)
+            }
+            AreaShape::Disk { center, radius, .. } => {
+                (*point - *center).length_squared() <= radius * radius
+            }
+        }
+    }
+
+    /// Returns a point on the shape's plane
+    fn origin(&self) -> Vector3 {
+        match self {
+            AreaShape::Rect { corner, .. } => *corner,
+            AreaShape::Disk { center, .. } => *center,
+        }
+    }
+}
+
+/// A one-sided flat light, which can either be part of the scene (seen
+/// by the camera and blocking rays like any object) or hidden from every
+/// ray so that it only lights the scene through direct light sampling
+#[derive(Clone)]
+pub struct AreaLight {
+    shape: AreaShape,
+    material: Arc<dyn Material>,
+    visible: bool,
+}
+
+impl AreaLight {
+    /// Creates a new area light from its shape, color times intensity, and if it can be seen
+    pub fn new(shape: AreaShape, radiance: Vector3, visible: bool) -> AreaLight {
+        AreaLight {
+            shape,
+            material: Arc::new(Emissive::one_sided(radiance, 1.0)),
+            visible,
+        }
+    }
+
+    /// If the light is part of the scene's objects
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Converts the chance of sampling a point on the light (uniform
+    /// over its area) into a chance over directions from `origin`
+    fn solid_angle_pdf(&self, origin: &Vector3, point: &Vector3) -> f64 {
+        let offset = *point - *origin;
+        let distance_squared = offset.length_squared();
+        let cos_light = -offset.dot(&self.shape.normal()) / distance_squared.sqrt();
+        if cos_light <= 0.0 {
+            return 0.0;
+        }
+
+        distance_squared / (cos_light * self.shape.area())
+    }
+}
+
+impl Light for AreaLight {
+    fn sample(&self, point: &Vector3, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        let point_on_light = self.shape.sample_point(sampler);
+        let pdf = self.solid_angle_pdf(point, &point_on_light);
+        if pdf <= 0.0 || !pdf.is_finite() {
+            return None;
+        }
+
+        let offset = point_on_light - *point;
+        let distance = offset.length();
+        let hit = Hit::new(
+            distance,
+            point_on_light,
+            self.shape.normal(),
+            true,
+            self.material.as_ref(),
+        );
+
+        Some(LightSample {
+            direction: offset / distance,
+            distance,
+            radiance: self.material.emitted(&hit),
+            pdf,
+            can_be_hit: self.visible,
+        })
+    }
+
+    fn pdf(&self, origin: &Vector3, hit: &Hit) -> f64 {
+        if !self.visible {
+            return 0.0;
+        }
+
+        // make sure the hit is actually on this light (allowing for rounding
+        // errors in the hit point, which grow with how far out it is)
+        let plane_distance = (hit.point - self.shape.origin()).dot(&self.shape.normal());
+        let tolerance = 1e-6 * (hit.point.length() + hit.distance).max(1.0);
+        if plane_distance.abs() > tolerance || !self.shape.contains(&hit.point) {
+            return 0.0;
+        }
+
+        self.solid_angle_pdf(origin, &hit.point)
+    }
+}
+
+impl Object for AreaLight {
+    fn get_hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        let normal = self.shape.normal();
+        let facing = ray.direction().dot(&normal);
+        if facing.abs() < HIT_EPSILON {
+            return None;
+        }
+
+        let distance = (self.shape.origin() - ray.origin()).dot(&normal) / facing;
+        if distance < HIT_EPSILON {
+            return None;
+        }
+
+        let point = ray.at(distance);
+        if !self.shape.contains(&point) {
+            return None;
+        }
+
+        Some(Hit::new(
+            distance,
+            point,
+            normal,
+            facing <= 0.0,
+            self.material.as_ref(),
+        ))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let bounds = match self.shape {
+            AreaShape::Rect {
+                corner,
+                edge_1,
+                edge_2,
+            } => Aabb::empty()
+                .grow(&corner)
+                .grow(&(corner + edge_1))
+                .grow(&(corner + edge_2))
+                .grow(&(corner + edge_1 + edge_2)),
+            AreaShape::Disk { center, radius, .. } => {
+                let radius_vector = Vector3::new(radius, radius, radius);
+                Aabb::new(center - radius_vector, center + radius_vector)
+            }
+        };
+
+        // give flat lights some thickness so rays along their plane still hit the box
+        let padding = Vector3::new(1e-6, 1e-6, 1e-6);
+        Aabb::new(bounds.min() - padding, bounds.max() + padding)
+    }
+}
+
+/// Every light in a scene, picked from uniformly
+pub struct LightList {
+    lights: Vec<Box<dyn Light>>,
+}
+
+impl LightList {
+    /// Creates a new list of lights
+    pub fn new(lights: Vec<Box<dyn Light>>) -> LightList {
+        LightList { lights }
+    }
+
+    /// Picks a random light and samples a direction towards it (the pdf
+    /// includes the chance of picking the light)
+    pub fn sample(&self, point: &Vector3, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        if self.lights.is_empty() {
+            return None;
+        }
+
+        let light = &self.lights[sampler.choose(self.lights.len())];
+        let mut sample = light.sample(point, sampler)?;
+        sample.pdf /= self.lights.len() as f64;
+
+        Some(sample)
+    }
+
+    /// Returns the probability density of `sample` choosing
+    /// the direction from `origin` to a hit
+    pub fn pdf(&self, origin: &Vector3, hit: &Hit) -> f64 {
+        if self.lights.is_empty() {
+            return 0.0;
+        }
+
+        let pdf: f64 = self.lights.iter().map(|light| light.pdf(origin, hit)).sum();
+        pdf / self.lights.len() as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn far_away_area_lights_can_be_hit() {
+        for offset in [0.0, 1e4, 1e8, 1e11] {
+            let corner = Vector3::new(offset + 0.3, offset - 0.7, offset + 0.1);
+            let shape = AreaShape::Rect {
+                corner,
+                edge_1: Vector3::new(1.7, 0.9, 0.3),
+                edge_2: Vector3::new(0.2, 0.6, 2.9),
+            };
+            let light = AreaLight::new(shape, Vector3::new(1.0, 1.0, 1.0), true);
+
+            // aim at points across the light from the side it shines on
+            let mut misses = 0;
+            for step in 1..100 {
+                let (u, v) = (step as f64 / 100.0, (step * 37 % 100) as f64 / 100.0);
+                let target =
+                    corner + Vector3::new(1.7, 0.9, 0.3) * u + Vector3::new(0.2, 0.6, 2.9) * v;
+                let origin = target + Vector3::new(0.3, -5.0, 0.2) * (1.0 + u);
+                let ray = Ray::new(origin, target - origin);
+
+                if let Some(hit) = light.get_hit(&ray) {
+                    if light.pdf(&origin, &hit) <= 0.0 {
+                        misses += 1;
+                    }
+                }
+            }
+            assert_eq!(misses, 0, "{} hits had a pdf of 0 at {}", misses, offset);
+        }
+    }
+}
@@ -1,183 +1,457 @@
+mod aabb;
+mod bvh;
 mod camera;
 mod constants;
+mod environment;
+mod film;
+mod filter;
 mod hit;
+mod image;
+mod input;
+mod lights;
 mod material;
+mod mesh;
+mod microfacet;
+mod obj;
 mod objects;
+mod output;
+mod principled;
 mod random;
 mod ray;
+mod render;
+mod sampler;
+mod scene;
+mod settings;
+mod sky;
 mod sphere;
+mod tonemap;
+mod triangle;
 mod vector3;
 
 extern crate anyhow;
 
-use crate::camera::Camera;
-use crate::constants::{ANTIALIASING_STRENGTH, IMAGE_HEIGHT, IMAGE_WIDTH, RAYS_PER_PIXEL};
-use crate::material::Material;
-use crate::objects::Object;
-use crate::sphere::Sphere;
-use anyhow::Context;
+use crate::film::Film;
+use crate::filter::FilterKind;
+use crate::image::Image;
+use crate::output::ImageFormat;
+use crate::ray::PathStats;
+use crate::render::{render_pass, tile_count};
+use crate::sampler::SamplerKind;
+use crate::scene::{EnvironmentDescription, ModelDescription, SceneDescription};
+use crate::tonemap::{ToneMapOperator, ToneMapper};
+use anyhow::{bail, Context};
 use clap::Parser;
-use progress_bar::{finalize_progress_bar, inc_progress_bar, init_progress_bar};
-use rand::rngs::ThreadRng;
-use rand::Rng;
-use ray::Ray;
-use std::fs::File;
-use std::thread::JoinHandle;
-use std::{
-    fs,
-    io::{Seek, Write},
-    path::PathBuf,
-    thread,
-};
+use progress_bar::{finalize_progress_bar, init_progress_bar};
+use std::{env, fs::File, io::BufWriter, io::Write, path::Path, path::PathBuf, thread};
 use vector3::Vector3 as Color;
-use vector3::Vector3;
-
-pub fn write_color(file: &mut File, color: &Color) -> anyhow::Result<()> {
-    let integer_red = (255.999 * color[0]) as u8;
-    let integer_green = (255.999 * color[1]) as u8;
-    let integer_blue = (255.999 * color[2]) as u8;
-
-    // write rgb value to file
-    file.write(format!("{} {} {}\n", integer_red, integer_green, integer_blue).as_bytes())
-        .with_context(|| "Issue writing to file".to_string())?;
-
-    Ok(())
-}
 
 #[derive(Parser)]
 #[command(author = "<utbryceh@gmail.com>")]
 #[command(version, about, long_about = None)]
 struct Cli {
+    /// The file to write the rendered image to
     file: PathBuf,
+
+    /// The format to save the image in (guessed from
+    /// the file's extension if not given)
+    #[arg(long, value_enum)]
+    format: Option<ImageFormat>,
+
+    /// How colors brighter than white are compressed before saving
+    /// (not applied to floating point formats)
+    #[arg(long, value_enum, default_value = "clamp")]
+    tonemap: ToneMapOperator,
+
+    /// Brightens (or darkens, if negative) the image by this many stops before
+    /// tone mapping (floating point formats are still brightened)
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    exposure: f64,
+
+    /// Saves linear colors instead of applying the sRGB transfer curve
+    #[arg(long)]
+    no_srgb: bool,
+
+    /// A `.toml` or `.json` scene description to render
+    /// (renders the built-in scene if not given)
+    #[arg(long)]
+    scene: Option<PathBuf>,
+
+    /// Writes the scene being rendered to a `.toml` or `.json` file
+    #[arg(long)]
+    write_scene: Option<PathBuf>,
+
+    /// A Wavefront `.obj` model to add to the scene (can be given multiple times)
+    #[arg(long)]
+    obj: Vec<PathBuf>,
+
+    /// The width of the image in pixels
+    #[arg(long)]
+    width: Option<u32>,
+
+    /// The height of the image in pixels
+    #[arg(long)]
+    height: Option<u32>,
+
+    /// The number of rays (samples) taken per pixel (the most taken when a
+    /// noise threshold is given, and also the fewest unless `--min-spp` is given)
+    #[arg(long)]
+    spp: Option<u32>,
+
+    /// The fewest rays taken per pixel before it can stop for being below the noise threshold
+    #[arg(long)]
+    min_spp: Option<u32>,
+
+    /// Stops sampling pixels once their noise is below this
+    /// (something like 0.01, or 0 to always take `--spp` rays)
+    #[arg(long)]
+    noise_threshold: Option<f64>,
+
+    /// How the random numbers for each sample are picked
+    #[arg(long, value_enum)]
+    sampler: Option<SamplerKind>,
+
+    /// How samples are blended into the pixels around them
+    #[arg(long, value_enum)]
+    filter: Option<FilterKind>,
+
+    /// How many pixels the filter reaches from each sample, from 0.5
+    /// to 8 (depends on the filter if not given)
+    #[arg(long)]
+    filter_radius: Option<f64>,
+
+    /// Picks the random numbers used to render (the same seed and
+    /// settings always give the same image, whatever the thread count)
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Saves an image of how many rays each pixel took (blue for few, red for the most)
+    #[arg(long)]
+    sample_heatmap: Option<PathBuf>,
+
+    /// The number of rays taken per pixel in each pass, after which the
+    /// image so far (and a checkpoint, if checkpoints are being saved) is saved
+    #[arg(long)]
+    samples_per_pass: Option<u32>,
+
+    /// Continues a render from its checkpoint, which keeps being saved (the
+    /// scene must be the same, but `--spp` can be raised to add more samples)
+    #[arg(long)]
+    resume: bool,
+
+    /// The number of threads to render with (the number of cores if not given)
+    #[arg(long)]
+    threads: Option<usize>,
+
+    /// Saves a checkpoint here after every pass so the render can be resumed (when
+    /// only resuming, the checkpoint is the image's path with `.checkpoint` added)
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+
+    /// The number of bounces before paths can be ended by Russian roulette
+    #[arg(long)]
+    min_bounces: Option<u32>,
+
+    /// The most times a ray can bounce (only a safety cap, since
+    /// paths are usually ended by Russian roulette first)
+    #[arg(long)]
+    max_bounces: Option<u32>,
+
+    /// An equirectangular `.hdr` or `.pfm` image to light the scene with
+    #[arg(long)]
+    environment: Option<PathBuf>,
+
+    /// Degrees to spin the environment map around the up axis
+    #[arg(long, allow_negative_numbers = true)]
+    environment_rotation: Option<f64>,
+
+    /// Multiplies the brightness of the environment map
+    #[arg(long)]
+    environment_intensity: Option<f64>,
+
+    /// Lights the scene with a daylight sky with the sun this many degrees above the horizon
+    #[arg(long, allow_negative_numbers = true)]
+    sun_elevation: Option<f64>,
+
+    /// Turns the sun of the daylight sky this many degrees from +z towards +x
+    #[arg(long, allow_negative_numbers = true)]
+    sun_azimuth: Option<f64>,
+
+    /// How hazy the daylight sky is (from 1.7 to 10)
+    #[arg(long)]
+    turbidity: Option<f64>,
+
+    /// The color of the sky gradient looking straight up (`r,g,b`)
+    #[arg(long)]
+    sky_top: Option<Color>,
+
+    /// The color of the sky gradient looking straight down (`r,g,b`)
+    #[arg(long)]
+    sky_bottom: Option<Color>,
 }
 
-fn ray_color(ray: Ray, rng: &mut ThreadRng) -> Color {
-    let objects: [&dyn Object; 7] = [
-        &Sphere::new(
-            Vector3::new(0.0, 16.0, 0.0),
-            8.0,
-            Material::new(
-                Color::new(0.0, 0.0, 0.0),
-                0.0,
-                Color::new(0.93, 0.95, 0.2),
-                8.0,
-            ),
-        ),
-        &Sphere::new(
-            Vector3::new(0.0, -31.0, 0.0),
-            30.0,
-            Material::new_lightless(Color::new(0.8, 0.2, 0.2), 0.0),
-        ),
-        &Sphere::new(
-            Vector3::new(-10.0, -1.0, 16.0),
-            2.0,
-            Material::new_lightless(Color::new(0.2, 0.8, 0.2), 0.0),
-        ),
-        &Sphere::new(
-            Vector3::new(-5.0, -1.0, 16.0),
-            2.0,
-            Material::new_lightless(Color::new(0.2, 0.8, 0.2), 0.5),
-        ),
-        &Sphere::new(
-            Vector3::new(0.0, -1.0, 16.0),
-            2.0,
-            Material::new_lightless(Color::new(0.2, 0.8, 0.2), 1.0),
-        ),
-        &Sphere::new(
-            Vector3::new(5.0, -1.0, 16.0),
-            2.0,
-            Material::new_lightless(Color::new(0.2, 0.8, 0.2), 0.5),
-        ),
-        &Sphere::new(
-            Vector3::new(10.0, -1.0, 16.0),
-            2.0,
-            Material::new_lightless(Color::new(0.2, 0.8, 0.2), 0.0),
-        ),
-    ];
+impl Cli {
+    /// Tone maps an image and saves it (only applying the
+    /// exposure if it's being saved with unclamped colors)
+    fn save_image(&self, mut image: Image, format: ImageFormat) -> anyhow::Result<()> {
+        let tone_mapper = ToneMapper::new(self.tonemap, self.exposure, !self.no_srgb);
+        if format.is_hdr() {
+            tone_mapper.apply_exposure(&mut image);
+        } else {
+            tone_mapper.apply(&mut image);
+        }
+
+        save_image(&image, format, &self.file)
+    }
+
+    /// Overrides the settings of a scene with any settings given on the command line
+    fn apply_overrides(&self, scene_description: &mut SceneDescription) -> anyhow::Result<()> {
+        // models given on the command line are relative to the working directory
+        for path in &self.obj {
+            let path = env::current_dir()
+                .with_context(|| "Issue getting the current directory")?
+                .join(path);
+            scene_description.models.push(ModelDescription::new(path));
+        }
+
+        let settings = &mut scene_description.render;
+
+        if let Some(width) = self.width {
+            settings.image_width = width;
+        }
+        if let Some(height) = self.height {
+            settings.image_height = height;
+        }
+        if let Some(spp) = self.spp {
+            settings.rays_per_pixel = spp;
+            settings.min_rays_per_pixel = settings.min_rays_per_pixel.min(spp);
+        }
+        if let Some(min_spp) = self.min_spp {
+            settings.min_rays_per_pixel = min_spp;
+        }
+        if let Some(noise_threshold) = self.noise_threshold {
+            settings.noise_threshold = noise_threshold;
+        }
+        if let Some(sampler) = self.sampler {
+            settings.sampler = sampler;
+        }
+        if let Some(filter) = self.filter {
+            settings.filter = filter;
+        }
+        if let Some(filter_radius) = self.filter_radius {
+            settings.filter_radius = Some(filter_radius);
+        }
+        if let Some(seed) = self.seed {
+            settings.seed = seed;
+        }
+        if let Some(samples_per_pass) = self.samples_per_pass {
+            settings.samples_per_pass = samples_per_pass;
+        }
+        if let Some(min_bounces) = self.min_bounces {
+            settings.min_bounces = min_bounces;
+        }
+        if let Some(max_bounces) = self.max_bounces {
+            settings.max_bounces = max_bounces;
+        }
+
+        let environment = &mut scene_description.environment;
+
+        let wants_map = self.environment.is_some();
+        let wants_gradient = self.sky_top.is_some() || self.sky_bottom.is_some();
+        let wants_sky =
+            self.sun_elevation.is_some() || self.sun_azimuth.is_some() || self.turbidity.is_some();
+        if [wants_map, wants_gradient, wants_sky]
+            .iter()
+            .filter(|&&wanted| wanted)
+            .count()
+            > 1
+        {
+            bail!("Only one of `--environment`, the sky gradient colors, or the daylight sky settings can be given");
+        }
+
+        if let Some(path) = &self.environment {
+            let path = env::current_dir()
+                .with_context(|| "Issue getting the current directory")?
+                .join(path);
+            *environment = EnvironmentDescription::Map {
+                path,
+                rotation: 0.0,
+                intensity: 1.0,
+            };
+        }
+
+        // sky colors switch back to a gradient if the scene has something else
+        if wants_gradient {
+            if !matches!(environment, EnvironmentDescription::Gradient { .. }) {
+                *environment = EnvironmentDescription::default();
+            }
+
+            if let EnvironmentDescription::Gradient {
+                lower_color,
+                upper_color,
+            } = environment
+            {
+                *upper_color = self.sky_top.unwrap_or(*upper_color);
+                *lower_color = self.sky_bottom.unwrap_or(*lower_color);
+            }
+        }
+
+        // sun settings switch to a daylight sky if the scene has something else
+        if wants_sky {
+            if !matches!(environment, EnvironmentDescription::Sky { .. }) {
+                *environment = EnvironmentDescription::default_sky();
+            }
+
+            if let EnvironmentDescription::Sky {
+                sun_elevation,
+                sun_azimuth,
+                turbidity,
+                ..
+            } = environment
+            {
+                *sun_elevation = self.sun_elevation.unwrap_or(*sun_elevation);
+                *sun_azimuth = self.sun_azimuth.unwrap_or(*sun_azimuth);
+                *turbidity = self.turbidity.unwrap_or(*turbidity);
+            }
+        }
 
-    ray.trace(&objects, rng)
+        if self.environment_rotation.is_some() || self.environment_intensity.is_some() {
+            match environment {
+                EnvironmentDescription::Map {
+                    rotation,
+                    intensity,
+                    ..
+                } => {
+                    *rotation = self.environment_rotation.unwrap_or(*rotation);
+                    *intensity = self.environment_intensity.unwrap_or(*intensity);
+                }
+                _ => bail!("Environment rotation and intensity only apply to environment maps"),
+            }
+        }
+
+        Ok(())
+    }
 }
 
-fn main() -> anyhow::Result<()> {
-    // initialize progress bar
-    init_progress_bar(IMAGE_HEIGHT as usize * 2);
+/// Writes an image to a file in the given format
+fn save_image(image: &Image, format: ImageFormat, path: &Path) -> anyhow::Result<()> {
+    let output_file =
+        File::create(path).with_context(|| format!("Issue opening file `{}`", path.display()))?;
 
+    let mut writer = BufWriter::new(output_file);
+    format
+        .encoder()
+        .encode(image, &mut writer)
+        .with_context(|| format!("Issue writing to file `{}`", path.display()))?;
+    writer
+        .flush()
+        .with_context(|| format!("Issue writing to file `{}`", path.display()))
+}
+
+fn main() -> anyhow::Result<()> {
     // get command-line arguments
     let args = Cli::parse();
 
-    // open the file
-    let mut output_file = fs::OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open(&args.file)
-        .with_context(|| format!("Issue opening file `{}`", args.file.display()))?;
-
-    // clear file
-    output_file
-        .set_len(0)
-        .with_context(|| format!("Issue modifying file `{}`", args.file.display()))?;
-    output_file
-        .rewind()
-        .with_context(|| "Issue seeking beginning of file after clearing")?;
-
-    // output ppm info
-    output_file
-        .write(format!("P3\n{} {}\n255\n", IMAGE_WIDTH, IMAGE_HEIGHT).as_bytes())
-        .with_context(|| format!("Issue writing to file `{}`", args.file.display()))?;
-
-    // create a camera
-    let camera = Camera::new(Vector3::default());
-
-    // multithreading handles
-    let mut handles: Vec<JoinHandle<Vec<Color>>> = Vec::new();
-
-    for pixel_y in (0..IMAGE_HEIGHT).rev() {
-        let pixel_y_clone = pixel_y;
-        let camera_clone = camera;
-
-        handles.push(thread::spawn(move || {
-            // used to store the pixel colors for the row
-            let mut pixel_colors: Vec<Color> = Vec::new();
-
-            // used for super sampling
-            let mut rng = rand::thread_rng();
-
-            for pixel_x in 0..IMAGE_WIDTH {
-                let mut pixel_color = Color::default();
-
-                for _ in 0..RAYS_PER_PIXEL {
-                    let width_ratio = pixel_x as f64 / (IMAGE_WIDTH - 1) as f64
-                        + rng.gen::<f64>() * ANTIALIASING_STRENGTH / IMAGE_WIDTH as f64;
-                    let height_ratio = pixel_y_clone as f64 / (IMAGE_HEIGHT - 1) as f64
-                        + rng.gen::<f64>() * ANTIALIASING_STRENGTH / IMAGE_HEIGHT as f64;
-
-                    let ray = camera_clone.get_ray(width_ratio, height_ratio);
-                    let sample_color = ray_color(ray, &mut rng);
-
-                    pixel_color += sample_color;
-                }
+    // figure out the image formats before spending time loading the scene
+    let format = match args.format {
+        Some(format) => format,
+        None => ImageFormat::from_path(&args.file)?,
+    };
+    let heatmap_format = match &args.sample_heatmap {
+        Some(path) => Some(ImageFormat::from_path(path)?),
+        None => None,
+    };
 
-                pixel_color /= RAYS_PER_PIXEL as f64;
-                pixel_colors.push(pixel_color);
-            }
+    // load the scene
+    let mut scene_description = match &args.scene {
+        Some(path) => SceneDescription::load(path)?,
+        None => SceneDescription::default(),
+    };
+
+    args.apply_overrides(&mut scene_description)?;
 
-            inc_progress_bar();
-            pixel_colors
-        }));
+    scene_description.validate()?;
 
-        inc_progress_bar();
+    if let Some(path) = &args.write_scene {
+        scene_description.save(path)?;
     }
 
-    for handle in handles {
-        let colors = handle.join().unwrap();
+    // build the scene once and share it between every thread
+    let fingerprint = scene_description.fingerprint()?;
+    let scene = scene_description.build()?;
+    let settings = scene.settings;
+
+    let threads = match args.threads {
+        Some(0) => bail!("Must render with at least one thread"),
+        Some(threads) => threads,
+        None => thread::available_parallelism().map_or(1, |cores| cores.get()),
+    };
 
-        for color in colors {
-            write_color(&mut output_file, &color).with_context(|| "Issue writing to file")?;
+    // checkpoints are only saved when asked for, and are kept next
+    // to the image unless they're given somewhere else
+    let checkpoint_path = match (&args.checkpoint, args.resume) {
+        (Some(path), _) => Some(path.clone()),
+        (None, true) => {
+            let mut path = args.file.as_os_str().to_owned();
+            path.push(".checkpoint");
+            Some(PathBuf::from(path))
+        }
+        (None, false) => None,
+    };
+
+    let (mut film, mut stats) = match (&checkpoint_path, args.resume) {
+        (Some(checkpoint_path), true) => Film::load_checkpoint(
+            checkpoint_path,
+            fingerprint,
+            settings.image_width,
+            settings.image_height,
+        )?,
+        _ => (
+            Film::new(settings.image_width, settings.image_height),
+            PathStats::default(),
+        ),
+    };
+
+    // initialize progress bar (for the most passes the render could
+    // take, since pixels can finish early with adaptive sampling)
+    let remaining_samples = settings
+        .rays_per_pixel
+        .saturating_sub(film.fewest_samples());
+    let passes = remaining_samples.div_ceil(settings.samples_per_pass);
+    let tiles = tile_count(settings.image_width, settings.image_height);
+    init_progress_bar(passes as usize * tiles);
+
+    // render passes until every pixel has enough samples, saving the image
+    // so far (and a checkpoint to resume from) after each one
+    let mut samples = film.next_pass(&settings);
+    while samples.iter().any(|samples| !samples.is_empty()) {
+        let (pass, regions, pass_stats) = render_pass(&scene, &samples, threads);
+        film.add_pass(&pass, &regions);
+        stats.merge(&pass_stats);
+
+        if let Some(checkpoint_path) = &checkpoint_path {
+            film.save_checkpoint(checkpoint_path, fingerprint, &stats)?;
+        }
+
+        samples = film.next_pass(&settings);
+        if samples.iter().any(|samples| !samples.is_empty()) {
+            args.save_image(film.image(), format)?;
         }
     }
 
     finalize_progress_bar();
 
+    println!(
+        "Average path length: {:.2} bounces over {} paths",
+        stats.average_length(),
+        stats.paths
+    );
+
+    args.save_image(film.image(), format)?;
+
+    if let (Some(path), Some(heatmap_format)) = (&args.sample_heatmap, heatmap_format) {
+        let heatmap = film.sample_heatmap(settings.rays_per_pixel);
+        save_image(&heatmap, heatmap_format, path)?;
+    }
+
     Ok(())
 }
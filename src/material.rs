@@ -1,37 +1,451 @@
+use crate::hit::Hit;
+use crate::microfacet::{
+    ggx_reflection, ggx_reflection_pdf, roughness_to_alpha, sample_ggx_half_vector,
+};
+use crate::random::{cosine_hemisphere_pdf, random_cosine_hemisphere};
+use crate::sampler::Sampler;
 use crate::vector3::Vector3;
+use anyhow::bail;
+use std::f64::consts::PI;
 
-/// Represents a material that can be applied to an Object
-#[derive(Copy, Clone)]
-pub struct Material {
-    pub color: Vector3,
-    pub smoothness: f64,
-    pub emission_color: Vector3,
-    pub emission_strength: f64,
-}
-
-impl Material {
-    /// Creates a new Material
-    pub fn new(
-        color: Vector3,
-        smoothness: f64,
-        emission_color: Vector3,
-        emission_strength: f64,
-    ) -> Material {
-        Material {
+/// A direction chosen by a material for a ray to continue in
+pub struct ScatterSample {
+    pub direction: Vector3,
+
+    /// The value of the BSDF for the chosen direction (for specular
+    /// samples, this is instead the whole weight to multiply the path by)
+    pub value: Vector3,
+
+    /// The probability density of choosing the direction (ignored for specular samples)
+    pub pdf: f64,
+
+    /// If the direction came from a perfectly sharp reflection or refraction,
+    /// which can only be sampled and never hit by chance
+    pub specular: bool,
+}
+
+impl ScatterSample {
+    /// Returns the Monte Carlo weight of the sample, f * |cos| / pdf,
+    /// that the path's throughput gets multiplied by (or None if the
+    /// direction could never have been sampled)
+    pub fn weight(&self, normal: &Vector3) -> Option<Vector3> {
+        if self.specular {
+            return Some(self.value);
+        }
+
+        if self.pdf <= 0.0 {
+            return None;
+        }
+
+        Some(self.value * (self.direction.dot(normal).abs() / self.pdf))
+    }
+}
+
+/// Represents how light interacts with the surface of an Object. Directions
+/// `incoming` (the direction of the ray that hit the surface, pointing at
+/// it) and `outgoing` (pointing away from the surface) are normalized
+pub trait Material: Send + Sync {
+    /// Chooses a direction for a ray to continue in (or None if the ray is absorbed)
+    fn sample(
+        &self,
+        hit: &Hit,
+        incoming: &Vector3,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterSample>;
+
+    /// Evaluates the BSDF for a pair of directions (always 0 for specular materials)
+    fn evaluate(&self, hit: &Hit, incoming: &Vector3, outgoing: &Vector3) -> Vector3;
+
+    /// Returns the probability density of `sample` choosing `outgoing`
+    fn pdf(&self, hit: &Hit, incoming: &Vector3, outgoing: &Vector3) -> f64;
+
+    /// Returns the light the surface gives off
+    fn emitted(&self, _hit: &Hit) -> Vector3 {
+        Vector3::default()
+    }
+
+    /// If the surface gives off any light (and so should be sampled as a light)
+    fn is_emissive(&self) -> bool {
+        false
+    }
+}
+
+/// Approximates how much light reflects off of the boundary between two
+/// materials with Schlick's approximation of the Fresnel equations, given
+/// the cosine of the angle between the ray and the normal and the ratio of
+/// the refraction index being left over the one being entered
+pub fn schlick_reflectance(cos_theta: f64, refraction_ratio: f64) -> f64 {
+    let r0 = ((1.0 - refraction_ratio) / (1.0 + refraction_ratio)).powi(2);
+    r0 + (1.0 - r0) * (1.0 - cos_theta).powi(5)
+}
+
+/// A perfectly matte surface that scatters light evenly
+pub struct Lambertian {
+    color: Vector3,
+}
+
+impl Lambertian {
+    /// Creates a new lambertian material from its color
+    pub fn new(color: Vector3) -> Lambertian {
+        Lambertian { color }
+    }
+}
+
+impl Material for Lambertian {
+    fn sample(
+        &self,
+        hit: &Hit,
+        incoming: &Vector3,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterSample> {
+        let direction = random_cosine_hemisphere(&hit.facing_normal(), sampler);
+
+        Some(ScatterSample {
+            direction,
+            value: self.evaluate(hit, incoming, &direction),
+            pdf: self.pdf(hit, incoming, &direction),
+            specular: false,
+        })
+    }
+
+    fn evaluate(&self, hit: &Hit, _incoming: &Vector3, outgoing: &Vector3) -> Vector3 {
+        if outgoing.dot(&hit.facing_normal()) <= 0.0 {
+            return Vector3::default();
+        }
+
+        self.color / PI
+    }
+
+    fn pdf(&self, hit: &Hit, _incoming: &Vector3, outgoing: &Vector3) -> f64 {
+        cosine_hemisphere_pdf(outgoing.dot(&hit.facing_normal()))
+    }
+}
+
+/// A perfectly smooth mirror tinted by a color
+pub struct Mirror {
+    color: Vector3,
+}
+
+impl Mirror {
+    /// Creates a new mirror material from its color
+    pub fn new(color: Vector3) -> Mirror {
+        Mirror { color }
+    }
+}
+
+impl Material for Mirror {
+    fn sample(
+        &self,
+        hit: &Hit,
+        incoming: &Vector3,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ScatterSample> {
+        Some(ScatterSample {
+            direction: incoming.reflect_across(&hit.facing_normal()),
+            value: self.color,
+            pdf: 1.0,
+            specular: true,
+        })
+    }
+
+    fn evaluate(&self, _hit: &Hit, _incoming: &Vector3, _outgoing: &Vector3) -> Vector3 {
+        Vector3::default()
+    }
+
+    fn pdf(&self, _hit: &Hit, _incoming: &Vector3, _outgoing: &Vector3) -> f64 {
+        0.0
+    }
+}
+
+/// A rough metal using the GGX microfacet model, where `color`
+/// is the reflectance when looking straight at the surface
+pub struct Metal {
+    color: Vector3,
+    alpha: f64,
+}
+
+impl Metal {
+    /// Creates a new metal material from its color and roughness (from 0 to 1)
+    pub fn new(color: Vector3, roughness: f64) -> Metal {
+        Metal {
             color,
-            smoothness,
-            emission_color,
-            emission_strength,
+            alpha: roughness_to_alpha(roughness.clamp(0.0, 1.0)),
+        }
+    }
+}
+
+impl Material for Metal {
+    fn sample(
+        &self,
+        hit: &Hit,
+        incoming: &Vector3,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterSample> {
+        let normal = hit.facing_normal();
+        let half = sample_ggx_half_vector(&normal, self.alpha, sampler.get_2d());
+        let direction = incoming.reflect_across(&half);
+
+        // rays reflected into the surface are absorbed
+        if direction.dot(&normal) <= 0.0 {
+            return None;
         }
+
+        Some(ScatterSample {
+            direction,
+            value: self.evaluate(hit, incoming, &direction),
+            pdf: self.pdf(hit, incoming, &direction),
+            specular: false,
+        })
+    }
+
+    fn evaluate(&self, hit: &Hit, incoming: &Vector3, outgoing: &Vector3) -> Vector3 {
+        ggx_reflection(
+            &hit.facing_normal(),
+            &-*incoming,
+            outgoing,
+            self.alpha,
+            self.color,
+        )
+    }
+
+    fn pdf(&self, hit: &Hit, incoming: &Vector3, outgoing: &Vector3) -> f64 {
+        ggx_reflection_pdf(&hit.facing_normal(), &-*incoming, outgoing, self.alpha)
     }
+}
+
+/// A clear material like glass or water that refracts light
+/// passing through it (tinted by `color`)
+pub struct Dielectric {
+    color: Vector3,
+    refraction_index: f64,
+}
 
-    /// Creates a new Material with no emission
-    pub fn new_lightless(color: Vector3, smoothness: f64) -> Material {
-        Material {
+impl Dielectric {
+    /// Creates a new dielectric material from its color (which only tints
+    /// light passing through it) and refraction index, which must be above 0
+    pub fn new(color: Vector3, refraction_index: f64) -> anyhow::Result<Dielectric> {
+        if !(refraction_index.is_finite() && refraction_index > 0.0) {
+            bail!(
+                "The refraction index of a dielectric must be above 0 (got {})",
+                refraction_index
+            );
+        }
+
+        Ok(Dielectric {
+            color,
+            refraction_index,
+        })
+    }
+}
+
+impl Material for Dielectric {
+    fn sample(
+        &self,
+        hit: &Hit,
+        incoming: &Vector3,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterSample> {
+        let normal = hit.facing_normal();
+
+        // the ray is entering the material if it hit the outside
+        let refraction_ratio = if hit.outside_face {
+            1.0 / self.refraction_index
+        } else {
+            self.refraction_index
+        };
+
+        let cos_theta = (-*incoming).dot(&normal).min(1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+        // reflect if refraction is impossible (total internal reflection) or if
+        // the Fresnel reflectance says to, and only tint light passing through
+        let (direction, value) = if refraction_ratio * sin_theta > 1.0
+            || schlick_reflectance(cos_theta, refraction_ratio) > sampler.get_1d()
+        {
+            (
+                incoming.reflect_across(&normal),
+                Vector3::new(1.0, 1.0, 1.0),
+            )
+        } else {
+            (incoming.refract(&normal, refraction_ratio), self.color)
+        };
+
+        Some(ScatterSample {
+            direction,
+            value,
+            pdf: 1.0,
+            specular: true,
+        })
+    }
+
+    fn evaluate(&self, _hit: &Hit, _incoming: &Vector3, _outgoing: &Vector3) -> Vector3 {
+        Vector3::default()
+    }
+
+    fn pdf(&self, _hit: &Hit, _incoming: &Vector3, _outgoing: &Vector3) -> f64 {
+        0.0
+    }
+}
+
+/// A surface that gives off light and absorbs anything that hits it
+pub struct Emissive {
+    color: Vector3,
+    strength: f64,
+    two_sided: bool,
+}
+
+impl Emissive {
+    /// Creates a new emissive material from the color and strength of its light
+    pub fn new(color: Vector3, strength: f64) -> Emissive {
+        Emissive {
+            color,
+            strength,
+            two_sided: true,
+        }
+    }
+
+    /// Creates a new emissive material that only gives off light from the outside of a surface
+    pub fn one_sided(color: Vector3, strength: f64) -> Emissive {
+        Emissive {
             color,
-            smoothness,
-            emission_color: Vector3::default(),
-            emission_strength: 0.0,
+            strength,
+            two_sided: false,
         }
     }
 }
+
+impl Material for Emissive {
+    fn sample(
+        &self,
+        _hit: &Hit,
+        _incoming: &Vector3,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ScatterSample> {
+        None
+    }
+
+    fn evaluate(&self, _hit: &Hit, _incoming: &Vector3, _outgoing: &Vector3) -> Vector3 {
+        Vector3::default()
+    }
+
+    fn pdf(&self, _hit: &Hit, _incoming: &Vector3, _outgoing: &Vector3) -> f64 {
+        0.0
+    }
+
+    fn emitted(&self, hit: &Hit) -> Vector3 {
+        if !self.two_sided && !hit.outside_face {
+            return Vector3::default();
+        }
+
+        self.color * self.strength
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.strength > 0.0 && self.color.length_squared() > 0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::principled::Principled;
+    use crate::sampler::IndependentSampler;
+
+    /// Estimates how much of the light arriving from `incoming` the material
+    /// reflects in total (its directional albedo) by sampling it
+    fn directional_albedo(material: &dyn Material, incoming: Vector3, samples: u32) -> Vector3 {
+        let normal = Vector3::new(0.0, 0.0, 1.0);
+        let hit = Hit::new(1.0, Vector3::default(), normal, true, material);
+        let mut sampler = IndependentSampler::new(11);
+        let mut total = Vector3::default();
+
+        for index in 0..samples {
+            sampler.start_sample(0, 0, index);
+
+            let weight = material
+                .sample(&hit, &incoming, &mut sampler)
+                .and_then(|sample| sample.weight(&normal));
+            if let Some(weight) = weight {
+                total += weight;
+            }
+        }
+
+        total / samples as f64
+    }
+
+    /// Checks that a white version of a material never reflects more light
+    /// than arrives, from straight on to grazing angles
+    fn assert_conserves_energy(material: &dyn Material) {
+        for angle in [0.0f64, 30.0, 60.0, 85.0] {
+            let angle = angle.to_radians();
+            let incoming = Vector3::new(angle.sin(), 0.0, -angle.cos());
+            let albedo = directional_albedo(material, incoming, 20000);
+
+            for channel in 0..3 {
+                assert!(
+                    albedo[channel] <= 1.01,
+                    "reflected {} of the light at {} degrees",
+                    albedo[channel],
+                    angle.to_degrees()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn metal_conserves_energy() {
+        let white = Vector3::new(1.0, 1.0, 1.0);
+        for roughness in [0.0, 0.1, 0.3, 0.6, 1.0] {
+            assert_conserves_energy(&Metal::new(white, roughness));
+        }
+    }
+
+    #[test]
+    fn principled_conserves_energy() {
+        let white = Vector3::new(1.0, 1.0, 1.0);
+        for metallic in [0.0, 0.5, 1.0] {
+            for roughness in [0.0, 0.3, 1.0] {
+                for clearcoat in [0.0, 1.0] {
+                    let material =
+                        Principled::new(white, metallic, roughness, 1.0, clearcoat, 0.03);
+                    assert_conserves_energy(&material);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dielectrics_need_a_positive_refraction_index() {
+        let white = Vector3::new(1.0, 1.0, 1.0);
+        for refraction_index in [0.0, -1.5, f64::NAN, f64::INFINITY] {
+            assert!(Dielectric::new(white, refraction_index).is_err());
+        }
+        assert!(Dielectric::new(white, 1.5).is_ok());
+    }
+
+    #[test]
+    fn dielectrics_only_tint_refracted_light() {
+        let red = Vector3::new(1.0, 0.0, 0.0);
+        let material = Dielectric::new(red, 1.5).unwrap();
+        let normal = Vector3::new(0.0, 0.0, 1.0);
+        let hit = Hit::new(1.0, Vector3::default(), normal, true, &material);
+        let incoming = Vector3::new(80.0f64.to_radians().sin(), 0.0, -80.0f64.to_radians().cos());
+        let mut sampler = IndependentSampler::new(3);
+        let (mut reflected, mut refracted) = (0, 0);
+
+        for index in 0..1000 {
+            sampler.start_sample(0, 0, index);
+            let sample = material.sample(&hit, &incoming, &mut sampler).unwrap();
+
+            if sample.direction.z() > 0.0 {
+                assert!((sample.value - Vector3::new(1.0, 1.0, 1.0)).length() < 1e-12);
+                reflected += 1;
+            } else {
+                assert!((sample.value - red).length() < 1e-12);
+                refracted += 1;
+            }
+        }
+
+        assert!(reflected > 0 && refracted > 0);
+    }
+}
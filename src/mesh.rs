@@ -0,0 +1,149 @@
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::hit::Hit;
+use crate::material::Material;
+use crate::objects::Object;
+use crate::ray::Ray;
+use crate::triangle::{intersect_triangle, triangle_bounding_box, triangle_hit};
+use crate::vector3::Vector3;
+use anyhow::bail;
+use std::sync::Arc;
+
+/// A triangle of a mesh, stored as indices into the mesh's buffers
+#[derive(Copy, Clone)]
+pub struct MeshFace {
+    pub positions: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub material: usize,
+}
+
+/// The vertex, normal and material buffers shared by every triangle of a mesh
+struct MeshData {
+    positions: Vec<Vector3>,
+    normals: Vec<Vector3>,
+    materials: Vec<Arc<dyn Material>>,
+    faces: Vec<MeshFace>,
+}
+
+impl MeshData {
+    /// Returns the positions of a face's vertices
+    fn vertices(&self, face: usize) -> [Vector3; 3] {
+        self.faces[face]
+            .positions
+            .map(|index| self.positions[index])
+    }
+
+    /// Returns the normals of a face's vertices (if it has any)
+    fn normals(&self, face: usize) -> Option<[Vector3; 3]> {
+        self.faces[face]
+            .normals
+            .map(|indices| indices.map(|index| self.normals[index]))
+    }
+}
+
+/// A single triangle of a mesh
+struct MeshTriangle {
+    data: Arc<MeshData>,
+    face: usize,
+}
+
+impl Object for MeshTriangle {
+    fn get_hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        let vertices = self.data.vertices(self.face);
+        let intersection = intersect_triangle(ray, &vertices)?;
+
+        Some(triangle_hit(
+            ray,
+            intersection,
+            &vertices,
+            self.data.normals(self.face).as_ref(),
+            self.data.materials[self.data.faces[self.face].material].as_ref(),
+        ))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        triangle_bounding_box(&self.data.vertices(self.face))
+    }
+}
+
+/// A triangle mesh with shared vertex and normal buffers (faces
+/// with normals are smooth shaded, faces without are flat shaded)
+pub struct Mesh {
+    triangles: Bvh,
+}
+
+impl Mesh {
+    /// Creates a new mesh, making sure every face only refers
+    /// to vertices, normals and materials that exist
+    pub fn new(
+        positions: Vec<Vector3>,
+        normals: Vec<Vector3>,
+        materials: Vec<Arc<dyn Material>>,
+        faces: Vec<MeshFace>,
+    ) -> anyhow::Result<Mesh> {
+        for (index, face) in faces.iter().enumerate() {
+            if let Some(&position) = face.positions.iter().find(|&&i| i >= positions.len()) {
+                bail!(
+                    "Face {} refers to vertex {} but the mesh only has {} vertices",
+                    index,
+                    position,
+                    positions.len()
+                );
+            }
+
+            if let Some(face_normals) = face.normals {
+                if let Some(&normal) = face_normals.iter().find(|&&i| i >= normals.len()) {
+                    bail!(
+                        "Face {} refers to normal {} but the mesh only has {} normals",
+                        index,
+                        normal,
+                        normals.len()
+                    );
+                }
+            }
+
+            if face.material >= materials.len() {
+                bail!(
+                    "Face {} refers to material {} but the mesh only has {} materials",
+                    index,
+                    face.material,
+                    materials.len()
+                );
+            }
+        }
+
+        let face_count = faces.len();
+        let data = Arc::new(MeshData {
+            positions,
+            normals: normals
+                .into_iter()
+                .map(|normal| normal.normalized())
+                .collect(),
+            materials,
+            faces,
+        });
+
+        let triangles = (0..face_count)
+            .map(|face| {
+                Box::new(MeshTriangle {
+                    data: Arc::clone(&data),
+                    face,
+                }) as Box<dyn Object + Send + Sync>
+            })
+            .collect();
+
+        Ok(Mesh {
+            triangles: Bvh::new(triangles),
+        })
+    }
+}
+
+impl Object for Mesh {
+    fn get_hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        self.triangles.get_hit(ray)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.triangles.bounding_box()
+    }
+}
@@ -0,0 +1,96 @@
+use crate::vector3::Vector3;
+use std::f64::consts::PI;
+
+//
+// Helpers for the GGX (Trowbridge-Reitz) microfacet distribution, where
+// `alpha` is the perceptual roughness squared and every cosine is measured
+// against the surface's (macro) normal
+//
+
+/// Keeps alpha from reaching 0, where the distribution becomes a perfect mirror
+pub fn roughness_to_alpha(roughness: f64) -> f64 {
+    (roughness * roughness).max(1e-3)
+}
+
+/// The fraction of microfacets facing along a half vector
+pub fn ggx_distribution(cos_half: f64, alpha: f64) -> f64 {
+    if cos_half <= 0.0 {
+        return 0.0;
+    }
+
+    let alpha_squared = alpha * alpha;
+    let denominator = cos_half * cos_half * (alpha_squared - 1.0) + 1.0;
+    alpha_squared / (PI * denominator * denominator)
+}
+
+/// Smith's masking function for a single direction
+fn smith_g1(cos_theta: f64, alpha: f64) -> f64 {
+    let cos_theta = cos_theta.abs();
+    let alpha_squared = alpha * alpha;
+
+    2.0 * cos_theta
+        / (cos_theta + (alpha_squared + (1.0 - alpha_squared) * cos_theta * cos_theta).sqrt())
+}
+
+/// The fraction of microfacets visible from both directions
+pub fn smith_masking(cos_in: f64, cos_out: f64, alpha: f64) -> f64 {
+    smith_g1(cos_in, alpha) * smith_g1(cos_out, alpha)
+}
+
+/// Schlick's approximation of Fresnel reflectance for a colored reflectance at normal incidence
+pub fn schlick_fresnel(cos_theta: f64, normal_reflectance: Vector3) -> Vector3 {
+    let weight = (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5);
+    normal_reflectance + (Vector3::new(1.0, 1.0, 1.0) - normal_reflectance) * weight
+}
+
+/// Samples a half vector (in world space) proportional to D(h) * cos(h)
+/// from two uniform numbers from 0 to 1
+pub fn sample_ggx_half_vector(normal: &Vector3, alpha: f64, (u1, u2): (f64, f64)) -> Vector3 {
+    let tan_squared = alpha * alpha * u1 / (1.0 - u1);
+    let cos_theta = 1.0 / (1.0 + tan_squared).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+
+    let (tangent, bitangent) = normal.orthonormal_basis();
+    tangent * (sin_theta * phi.cos()) + bitangent * (sin_theta * phi.sin()) + *normal * cos_theta
+}
+
+/// The pdf (over solid angle) of sampling `outgoing` by reflecting `view`
+/// (pointing away from the surface) across a GGX sampled half vector
+pub fn ggx_reflection_pdf(normal: &Vector3, view: &Vector3, outgoing: &Vector3, alpha: f64) -> f64 {
+    let half = *view + *outgoing;
+    if half.length_squared() == 0.0 {
+        return 0.0;
+    }
+    let half = half.normalized();
+
+    let cos_half = half.dot(normal);
+    let view_dot_half = view.dot(&half).abs();
+    if view_dot_half == 0.0 {
+        return 0.0;
+    }
+
+    ggx_distribution(cos_half, alpha) * cos_half / (4.0 * view_dot_half)
+}
+
+/// Evaluates the GGX specular reflection BRDF (without the cosine term)
+pub fn ggx_reflection(
+    normal: &Vector3,
+    view: &Vector3,
+    outgoing: &Vector3,
+    alpha: f64,
+    normal_reflectance: Vector3,
+) -> Vector3 {
+    let cos_view = normal.dot(view);
+    let cos_out = normal.dot(outgoing);
+    if cos_view <= 0.0 || cos_out <= 0.0 {
+        return Vector3::default();
+    }
+
+    let half = (*view + *outgoing).normalized();
+    let distribution = ggx_distribution(half.dot(normal), alpha);
+    let masking = smith_masking(cos_view, cos_out, alpha);
+    let fresnel = schlick_fresnel(view.dot(&half), normal_reflectance);
+
+    fresnel * (distribution * masking / (4.0 * cos_view * cos_out))
+}
@@ -0,0 +1,575 @@
+use crate::constants::DEFAULT_REFRACTION_INDEX;
+use crate::material::{Dielectric, Emissive, Material};
+use crate::mesh::{Mesh, MeshFace};
+use crate::principled::Principled;
+use crate::vector3::Vector3;
+use anyhow::{anyhow, bail, Context};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Parses the first three numbers of a statement's arguments into a vector
+fn parse_vector(arguments: &[&str]) -> anyhow::Result<Vector3> {
+    if arguments.len() < 3 {
+        bail!("Expected 3 numbers, got {}", arguments.len());
+    }
+
+    let mut terms = [0.0; 3];
+    for (term, argument) in terms.iter_mut().zip(arguments) {
+        *term = argument
+            .parse()
+            .with_context(|| format!("`{}` is not a number", argument))?;
+    }
+
+    Ok(Vector3::new(terms[0], terms[1], terms[2]))
+}
+
+/// Parses a single number from a statement's arguments
+fn parse_number(arguments: &[&str]) -> anyhow::Result<f64> {
+    match arguments.first() {
+        Some(argument) => argument
+            .parse()
+            .with_context(|| format!("`{}` is not a number", argument)),
+        None => bail!("Expected a number"),
+    }
+}
+
+/// Turns a 1-based (or negative, counting back from the
+/// end) OBJ index into a 0-based index into a buffer
+fn resolve_index(index: &str, count: usize, kind: &str, plural: &str) -> anyhow::Result<usize> {
+    let index: i64 = index
+        .parse()
+        .with_context(|| format!("`{}` is not a valid {} index", index, kind))?;
+
+    let resolved = match index {
+        0 => bail!("OBJ {} indices start at 1, not 0", kind),
+        index if index > 0 => index - 1,
+        index => count as i64 + index,
+    };
+
+    if resolved < 0 || resolved >= count as i64 {
+        bail!(
+            "{} index {} is out of range (only {} {} so far)",
+            kind,
+            index,
+            count,
+            if count == 1 { kind } else { plural }
+        );
+    }
+
+    Ok(resolved as usize)
+}
+
+/// The properties of an MTL material that are supported
+struct MtlMaterial {
+    color: Vector3,
+    emission: Vector3,
+    smoothness: f64,
+    transmission: f64,
+    refraction_index: f64,
+
+    // from the PBR extension to MTL
+    roughness: Option<f64>,
+    metallic: Option<f64>,
+    clearcoat: f64,
+    clearcoat_roughness: f64,
+}
+
+impl MtlMaterial {
+    /// Picks the material that best matches the properties: anything emissive
+    /// becomes Emissive, anything transparent becomes Dielectric, and the rest
+    /// becomes Principled, which is only metallic if `Pm` says so
+    fn build(&self) -> anyhow::Result<Arc<dyn Material>> {
+        let strength = self.emission[0].max(self.emission[1]).max(self.emission[2]);
+
+        let material: Arc<dyn Material> = if strength > 0.0 {
+            Arc::new(Emissive::new(self.emission / strength, strength))
+        } else if self.transmission > 0.0 {
+            Arc::new(Dielectric::new(self.color, self.refraction_index)?)
+        } else {
+            Arc::new(Principled::new(
+                self.color,
+                self.metallic.unwrap_or(0.0),
+                self.roughness.unwrap_or(1.0 - self.smoothness),
+                0.5,
+                self.clearcoat,
+                self.clearcoat_roughness,
+            ))
+        };
+
+        Ok(material)
+    }
+}
+
+/// Reads the materials from a Wavefront MTL file. `Kd` becomes the color,
+/// `Ke` becomes the emission, `Ns` (from 0 to 1000) is scaled down to the
+/// 0 to 1 smoothness, `Ni` becomes the refraction index, and `d` (or its
+/// opposite `Tr`) sets the transmission. `Pr`, `Pm`, `Pc` and `Pcr` from the
+/// PBR extension set the roughness (1 - smoothness if not given), metallic (0 if
+/// not given), clearcoat and clearcoat roughness
+pub fn load_mtl(path: &Path) -> anyhow::Result<HashMap<String, Arc<dyn Material>>> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Issue reading MTL file `{}`", path.display()))?;
+
+    parse_mtl(&contents, path)
+}
+
+/// Reads the materials from the contents of an MTL file, where
+/// `path` is only used to say where any errors are
+fn parse_mtl(contents: &str, path: &Path) -> anyhow::Result<HashMap<String, Arc<dyn Material>>> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    for (line_index, line) in contents.lines().enumerate() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (statement, arguments) = match tokens.split_first() {
+            Some((statement, arguments)) if !statement.starts_with('#') => (*statement, arguments),
+            _ => continue,
+        };
+
+        let result = (|| -> anyhow::Result<()> {
+            if statement == "newmtl" {
+                let name = arguments.join(" ");
+                if name.is_empty() {
+                    bail!("`newmtl` needs a name");
+                }
+
+                if let Some((name, material)) = current.take() {
+                    materials.insert(name, material.build()?);
+                }
+                current = Some((
+                    name,
+                    MtlMaterial {
+                        color: Vector3::new(0.8, 0.8, 0.8),
+                        emission: Vector3::default(),
+                        smoothness: 0.0,
+                        transmission: 0.0,
+                        refraction_index: DEFAULT_REFRACTION_INDEX,
+                        roughness: None,
+                        metallic: None,
+                        clearcoat: 0.0,
+                        clearcoat_roughness: 0.03,
+                    },
+                ));
+
+                return Ok(());
+            }
+
+            let material = match (&mut current, statement) {
+                (Some((_, material)), _) => material,
+                (None, "Kd" | "Ke" | "Ns" | "Ni" | "d" | "Tr" | "Pr" | "Pm" | "Pc" | "Pcr") => {
+                    bail!("`{}` comes before any `newmtl`", statement)
+                }
+                (None, _) => return Ok(()),
+            };
+
+            match statement {
+                "Kd" => material.color = parse_vector(arguments)?,
+                "Ke" => material.emission = parse_vector(arguments)?,
+                "Ns" => material.smoothness = (parse_number(arguments)? / 1000.0).clamp(0.0, 1.0),
+                "Ni" => {
+                    material.refraction_index = parse_number(arguments)?;
+                    if !(material.refraction_index.is_finite() && material.refraction_index > 0.0) {
+                        bail!("The refraction index must be above 0");
+                    }
+                }
+                "d" => material.transmission = (1.0 - parse_number(arguments)?).clamp(0.0, 1.0),
+                "Tr" => material.transmission = parse_number(arguments)?.clamp(0.0, 1.0),
+                "Pr" => material.roughness = Some(parse_number(arguments)?),
+                "Pm" => material.metallic = Some(parse_number(arguments)?),
+                "Pc" => material.clearcoat = parse_number(arguments)?,
+                "Pcr" => material.clearcoat_roughness = parse_number(arguments)?,
+
+                // everything else (textures, transparency, etc.) isn't supported
+                _ => (),
+            }
+
+            Ok(())
+        })();
+
+        result.with_context(|| {
+            format!(
+                "Issue parsing MTL file `{}` at line {}: `{}`",
+                path.display(),
+                line_index + 1,
+                line.trim()
+            )
+        })?;
+    }
+
+    if let Some((name, material)) = current {
+        materials.insert(name, material.build()?);
+    }
+
+    Ok(materials)
+}
+
+/// A model read from a Wavefront OBJ file
+pub struct ObjModel {
+    positions: Vec<Vector3>,
+    normals: Vec<Vector3>,
+    materials: Vec<Arc<dyn Material>>,
+    faces: Vec<MeshFace>,
+    groups: Vec<String>,
+
+    /// Which groups faces are in (a face can be in several), where each
+    /// face refers to one of these lists of indices into `groups`
+    group_lists: Vec<Vec<usize>>,
+    face_groups: Vec<usize>,
+}
+
+impl ObjModel {
+    /// Reads an OBJ file (along with any MTL files it uses), splitting
+    /// polygons into triangles. Faces without a material use `default_material`
+    pub fn load(path: &Path, default_material: Arc<dyn Material>) -> anyhow::Result<ObjModel> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Issue reading OBJ file `{}`", path.display()))?;
+
+        ObjModel::parse(&contents, path, default_material)
+    }
+
+    /// Reads a model from the contents of an OBJ file, where `path`
+    /// is used to find MTL files and to say where any errors are
+    fn parse(
+        contents: &str,
+        path: &Path,
+        default_material: Arc<dyn Material>,
+    ) -> anyhow::Result<ObjModel> {
+        let directory = path.parent().unwrap_or(Path::new(""));
+
+        let mut model = ObjModel {
+            positions: Vec::new(),
+            normals: Vec::new(),
+            materials: vec![default_material],
+            faces: Vec::new(),
+            groups: vec!["default".to_string()],
+            group_lists: vec![vec![0]],
+            face_groups: Vec::new(),
+        };
+
+        // materials from every `mtllib`, and which ones have been given an index
+        let mut library: HashMap<String, Arc<dyn Material>> = HashMap::new();
+        let mut material_indices: HashMap<String, usize> = HashMap::new();
+
+        let mut current_material = 0;
+        let mut current_groups = 0;
+
+        for (line_index, line) in contents.lines().enumerate() {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let (statement, arguments) = match tokens.split_first() {
+                Some((statement, arguments)) if !statement.starts_with('#') => {
+                    (*statement, arguments)
+                }
+                _ => continue,
+            };
+
+            let result = (|| -> anyhow::Result<()> {
+                match statement {
+                    "v" => model.positions.push(parse_vector(arguments)?),
+                    "vn" => model.normals.push(parse_vector(arguments)?),
+                    "f" => model.parse_face(arguments, current_material, current_groups)?,
+
+                    "g" | "o" => {
+                        // a `g` puts the faces after it in every group it names,
+                        // while an `o` names a single object (which can have spaces)
+                        let names = match (statement, arguments.is_empty()) {
+                            (_, true) => vec!["default".to_string()],
+                            ("g", false) => arguments.iter().map(|name| name.to_string()).collect(),
+                            (_, false) => vec![arguments.join(" ")],
+                        };
+
+                        let list = names
+                            .into_iter()
+                            .map(|name| model.group_index(name))
+                            .collect();
+                        model.group_lists.push(list);
+                        current_groups = model.group_lists.len() - 1;
+                    }
+
+                    "mtllib" => {
+                        for file in arguments {
+                            library.extend(load_mtl(&directory.join(file))?);
+                        }
+                    }
+
+                    "usemtl" => {
+                        let name = arguments.join(" ");
+
+                        current_material = match material_indices.get(&name) {
+                            Some(&index) => index,
+                            None => {
+                                let material = library.get(&name).cloned().ok_or_else(|| {
+                                    anyhow!("No material named `{}` in any `mtllib`", name)
+                                })?;
+
+                                model.materials.push(material);
+                                material_indices.insert(name, model.materials.len() - 1);
+                                model.materials.len() - 1
+                            }
+                        };
+                    }
+
+                    // everything else (texture coordinates, smoothing groups, etc.) isn't supported
+                    _ => (),
+                }
+
+                Ok(())
+            })();
+
+            result.with_context(|| {
+                format!(
+                    "Issue parsing OBJ file `{}` at line {}: `{}`",
+                    path.display(),
+                    line_index + 1,
+                    line.trim()
+                )
+            })?;
+        }
+
+        Ok(model)
+    }
+
+    /// Returns the files loading an OBJ file reads: the OBJ file
+    /// itself followed by every MTL file it uses
+    pub fn files(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Issue reading OBJ file `{}`", path.display()))?;
+        let directory = path.parent().unwrap_or(Path::new(""));
+
+        let mut files = vec![path.to_path_buf()];
+        for line in contents.lines() {
+            let mut tokens = line.split_whitespace();
+            if tokens.next() == Some("mtllib") {
+                files.extend(tokens.map(|file| directory.join(file)));
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Returns the index of the group with a name, adding the group if it's new
+    fn group_index(&mut self, name: String) -> usize {
+        match self.groups.iter().position(|group| *group == name) {
+            Some(index) => index,
+            None => {
+                self.groups.push(name);
+                self.groups.len() - 1
+            }
+        }
+    }
+
+    /// Parses the vertices of an `f` statement and splits the polygon into
+    /// triangles, which are put in the groups of the `groups`th group list
+    fn parse_face(
+        &mut self,
+        arguments: &[&str],
+        material: usize,
+        groups: usize,
+    ) -> anyhow::Result<()> {
+        if arguments.len() < 3 {
+            bail!("Faces need at least 3 vertices, got {}", arguments.len());
+        }
+
+        // each vertex is `position`, `position/texture`,
+        // `position//normal` or `position/texture/normal`
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+
+        for vertex in arguments {
+            let mut indices = vertex.split('/');
+
+            let position = indices.next().unwrap_or("");
+            positions.push(resolve_index(
+                position,
+                self.positions.len(),
+                "vertex",
+                "vertices",
+            )?);
+
+            match indices.nth(1) {
+                Some(normal) if !normal.is_empty() => normals.push(resolve_index(
+                    normal,
+                    self.normals.len(),
+                    "normal",
+                    "normals",
+                )?),
+                _ => (),
+            }
+        }
+
+        // only smooth shade the face if every vertex has a normal
+        let has_normals = normals.len() == positions.len();
+
+        // split the polygon into a fan of triangles around the first vertex
+        for index in 1..positions.len() - 1 {
+            self.faces.push(MeshFace {
+                positions: [positions[0], positions[index], positions[index + 1]],
+                normals: has_normals.then(|| [normals[0], normals[index], normals[index + 1]]),
+                material,
+            });
+            self.face_groups.push(groups);
+        }
+
+        Ok(())
+    }
+
+    /// Scales and then moves every vertex of the model. The scale must be
+    /// above 0, since mirroring the model would turn its faces inside out
+    pub fn transform(&mut self, scale: f64, offset: Vector3) -> anyhow::Result<()> {
+        if !(scale.is_finite() && scale > 0.0) {
+            bail!("The scale of a model must be above 0 (got {})", scale);
+        }
+
+        for position in self.positions.iter_mut() {
+            *position = *position * scale + offset;
+        }
+
+        Ok(())
+    }
+
+    /// Turns the model into a mesh, only keeping faces from the given
+    /// groups (or `o` objects), or every face if no groups are given
+    pub fn into_mesh(self, groups: &[String]) -> anyhow::Result<Mesh> {
+        for group in groups {
+            if !self.groups.contains(group) {
+                bail!(
+                    "The model has no group named `{}` (it has {})",
+                    group,
+                    self.groups.join(", ")
+                );
+            }
+        }
+
+        let faces = self
+            .faces
+            .into_iter()
+            .zip(self.face_groups)
+            .filter(|(_, list)| {
+                groups.is_empty()
+                    || self.group_lists[*list]
+                        .iter()
+                        .any(|group| groups.contains(&self.groups[*group]))
+            })
+            .map(|(face, _)| face)
+            .collect();
+
+        Mesh::new(self.positions, self.normals, self.materials, faces)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn parse(contents: &str) -> anyhow::Result<ObjModel> {
+        let default_material = Arc::new(Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
+        ObjModel::parse(contents, Path::new("test.obj"), default_material)
+    }
+
+    const SQUARES: &str = "
+        v 0 0 0
+        v 1 0 0
+        v 1 1 0
+        v 0 1 0
+        vn 0 0 1
+        g front both
+        f 1 2 3 4
+        g back both
+        f -1//1 -2//1 -3//1 -4//1
+    ";
+
+    #[test]
+    fn polygons_are_split_into_fans() {
+        let model = parse(SQUARES).unwrap();
+
+        assert_eq!(model.faces.len(), 4);
+        assert_eq!(model.faces[0].positions, [0, 1, 2]);
+        assert_eq!(model.faces[1].positions, [0, 2, 3]);
+        assert!(model.faces[0].normals.is_none());
+    }
+
+    #[test]
+    fn negative_indices_and_normals_are_resolved() {
+        let model = parse(SQUARES).unwrap();
+
+        assert_eq!(model.faces[2].positions, [3, 2, 1]);
+        assert_eq!(model.faces[3].positions, [3, 1, 0]);
+        assert_eq!(model.faces[2].normals, Some([0, 0, 0]));
+    }
+
+    #[test]
+    fn faces_can_be_in_several_groups() {
+        let faces_in = |groups: &[&str]| {
+            let groups: Vec<String> = groups.iter().map(|group| group.to_string()).collect();
+            let model = parse(SQUARES).unwrap();
+            model
+                .face_groups
+                .iter()
+                .filter(|list| {
+                    model.group_lists[**list]
+                        .iter()
+                        .any(|group| groups.contains(&model.groups[*group]))
+                })
+                .count()
+        };
+
+        assert_eq!(faces_in(&["front"]), 2);
+        assert_eq!(faces_in(&["back"]), 2);
+        assert_eq!(faces_in(&["both"]), 4);
+        assert_eq!(faces_in(&["default"]), 0);
+
+        let missing = parse(SQUARES).unwrap().into_mesh(&["side".to_string()]);
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn bad_faces_are_explained() {
+        let error = |contents: &str| format!("{:#}", parse(contents).err().unwrap());
+
+        assert!(error("v 0 0 0\nf 1 1").contains("Faces need at least 3 vertices, got 2"));
+        assert!(error("v 0 0 0\nf 1 1 0").contains("indices start at 1"));
+        assert!(error("v 0 0 0\nv 1 0 0\nf 1 2 3")
+            .contains("vertex index 3 is out of range (only 2 vertices so far)"));
+        assert!(error("v 0 0 0\nf 1 1 -2")
+            .contains("vertex index -2 is out of range (only 1 vertex so far)"));
+        assert!(error("v 0 0 0\nf 1//1 1//1 1//1")
+            .contains("normal index 1 is out of range (only 0 normals so far)"));
+        assert!(error("v 0 0 0\nv 1 0\n").contains("line 2"));
+    }
+
+    #[test]
+    fn models_must_be_scaled_up() {
+        for scale in [0.0, -1.0, f64::NAN] {
+            let mut model = parse(SQUARES).unwrap();
+            assert!(model.transform(scale, Vector3::default()).is_err());
+        }
+
+        let mut model = parse(SQUARES).unwrap();
+        model.transform(2.0, Vector3::new(1.0, 0.0, 0.0)).unwrap();
+        assert!((model.positions[2] - Vector3::new(3.0, 2.0, 0.0)).length() < 1e-12);
+    }
+
+    #[test]
+    fn mtl_errors_are_explained() {
+        let error = |contents: &str| {
+            format!(
+                "{:#}",
+                parse_mtl(contents, Path::new("test.mtl")).err().unwrap()
+            )
+        };
+
+        assert!(error("Kd 1 0 0").contains("`Kd` comes before any `newmtl`"));
+        assert!(error("newmtl red\nKd 1 0").contains("Expected 3 numbers, got 2"));
+        assert!(error("newmtl red\nNs shiny").contains("`shiny` is not a number"));
+        assert!(error("newmtl glass\nd 0.1\nNi 0").contains("line 3"));
+
+        let materials = parse_mtl(
+            "newmtl red\nKd 1 0 0\nNs 900\nnewmtl glass\nd 0.1\n",
+            Path::new("test.mtl"),
+        )
+        .unwrap();
+        assert_eq!(materials.len(), 2);
+    }
+}
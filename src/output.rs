@@ -0,0 +1,193 @@
+use crate::image::Image;
+use crate::vector3::Vector3 as Color;
+use anyhow::{bail, Context};
+use clap::ValueEnum;
+use std::io::Write;
+use std::path::Path;
+
+/// The file formats a rendered image can be saved as
+#[derive(Copy, Clone, ValueEnum)]
+pub enum ImageFormat {
+    /// Binary (P6) PPM
+    Ppm,
+    /// ASCII (P3) PPM
+    PpmAscii,
+    /// 8-bit RGB PNG
+    Png,
+    /// Floating point PFM (keeps unclamped colors)
+    Pfm,
+    /// Radiance RGBE HDR (keeps unclamped colors)
+    Hdr,
+}
+
+impl ImageFormat {
+    /// Picks a format based on the extension of a path
+    pub fn from_path(path: &Path) -> anyhow::Result<ImageFormat> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+
+        match extension.as_deref() {
+            Some("ppm") => Ok(ImageFormat::Ppm),
+            Some("png") => Ok(ImageFormat::Png),
+            Some("pfm") => Ok(ImageFormat::Pfm),
+            Some("hdr") => Ok(ImageFormat::Hdr),
+            _ => bail!(
+                "Can't tell the image format of `{}` from its extension \
+                 (use .ppm, .png, .pfm or .hdr, or pass --format)",
+                path.display()
+            ),
+        }
+    }
+
+    /// Returns whether the format stores unclamped floating point colors
+    /// (these are saved in linear light without any tone mapping)
+    pub fn is_hdr(self) -> bool {
+        matches!(self, ImageFormat::Pfm | ImageFormat::Hdr)
+    }
+
+    /// Returns the encoder that writes this format
+    pub fn encoder(self) -> Box<dyn Encoder> {
+        match self {
+            ImageFormat::Ppm => Box::new(PpmEncoder),
+            ImageFormat::PpmAscii => Box::new(AsciiPpmEncoder),
+            ImageFormat::Png => Box::new(PngEncoder),
+            ImageFormat::Pfm => Box::new(PfmEncoder),
+            ImageFormat::Hdr => Box::new(HdrEncoder),
+        }
+    }
+}
+
+/// Something that can write an image in a specific file format
+pub trait Encoder {
+    /// Writes the image to the writer
+    fn encode(&self, image: &Image, writer: &mut dyn Write) -> anyhow::Result<()>;
+}
+
+/// Converts a color channel from 0 to 1 into a byte (out of range values are clamped)
+pub fn quantize(value: f64) -> u8 {
+    (255.999 * value.clamp(0.0, 1.0)) as u8
+}
+
+/// Converts a color into red, green and blue bytes
+fn quantize_color(color: &Color) -> [u8; 3] {
+    [quantize(color[0]), quantize(color[1]), quantize(color[2])]
+}
+
+/// Writes binary (P6) PPM images
+pub struct PpmEncoder;
+
+impl Encoder for PpmEncoder {
+    fn encode(&self, image: &Image, writer: &mut dyn Write) -> anyhow::Result<()> {
+        write!(writer, "P6\n{} {}\n255\n", image.width(), image.height())?;
+
+        let bytes: Vec<u8> = image.pixels().iter().flat_map(quantize_color).collect();
+        writer.write_all(&bytes)?;
+
+        Ok(())
+    }
+}
+
+/// Writes ASCII (P3) PPM images
+pub struct AsciiPpmEncoder;
+
+impl Encoder for AsciiPpmEncoder {
+    fn encode(&self, image: &Image, writer: &mut dyn Write) -> anyhow::Result<()> {
+        write!(writer, "P3\n{} {}\n255\n", image.width(), image.height())?;
+
+        for color in image.pixels() {
+            let [red, green, blue] = quantize_color(color);
+            writeln!(writer, "{} {} {}", red, green, blue)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes 8-bit RGB PNG images
+pub struct PngEncoder;
+
+impl Encoder for PngEncoder {
+    fn encode(&self, image: &Image, writer: &mut dyn Write) -> anyhow::Result<()> {
+        let mut encoder = png::Encoder::new(writer, image.width(), image.height());
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let bytes: Vec<u8> = image.pixels().iter().flat_map(quantize_color).collect();
+        encoder
+            .write_header()
+            .with_context(|| "Issue writing PNG header")?
+            .write_image_data(&bytes)
+            .with_context(|| "Issue writing PNG data")?;
+
+        Ok(())
+    }
+}
+
+/// Writes little-endian floating point PFM images
+pub struct PfmEncoder;
+
+impl Encoder for PfmEncoder {
+    fn encode(&self, image: &Image, writer: &mut dyn Write) -> anyhow::Result<()> {
+        // a negative scale means the data is little-endian
+        write!(writer, "PF\n{} {}\n-1.0\n", image.width(), image.height())?;
+
+        // PFM rows go from the bottom of the image to the top
+        let mut bytes = Vec::with_capacity(image.pixels().len() * 12);
+        for row in (0..image.height()).rev() {
+            for color in image.row(row) {
+                for channel in 0..3 {
+                    bytes.extend_from_slice(&(color[channel] as f32).to_le_bytes());
+                }
+            }
+        }
+        writer.write_all(&bytes)?;
+
+        Ok(())
+    }
+}
+
+/// Writes Radiance RGBE (.hdr) images with uncompressed scanlines
+pub struct HdrEncoder;
+
+impl HdrEncoder {
+    /// Converts a color into the shared-exponent RGBE format
+    fn rgbe(color: &Color) -> [u8; 4] {
+        let red = color[0].max(0.0);
+        let green = color[1].max(0.0);
+        let blue = color[2].max(0.0);
+
+        let brightest = red.max(green).max(blue);
+        if brightest < 1e-32 || !brightest.is_finite() {
+            return [0, 0, 0, 0];
+        }
+
+        // split the brightest channel into a mantissa in [0.5, 1) and an exponent
+        let exponent = brightest.log2().floor() as i32 + 1;
+        let scale = 256.0 / 2f64.powi(exponent);
+
+        [
+            (red * scale).min(255.0) as u8,
+            (green * scale).min(255.0) as u8,
+            (blue * scale).min(255.0) as u8,
+            (exponent + 128).clamp(0, 255) as u8,
+        ]
+    }
+}
+
+impl Encoder for HdrEncoder {
+    fn encode(&self, image: &Image, writer: &mut dyn Write) -> anyhow::Result<()> {
+        write!(
+            writer,
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+            image.height(),
+            image.width()
+        )?;
+
+        let bytes: Vec<u8> = image.pixels().iter().flat_map(HdrEncoder::rgbe).collect();
+        writer.write_all(&bytes)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,160 @@
+use crate::hit::Hit;
+use crate::material::{Material, ScatterSample};
+use crate::microfacet::{
+    ggx_reflection, ggx_reflection_pdf, roughness_to_alpha, sample_ggx_half_vector, schlick_fresnel,
+};
+use crate::random::{cosine_hemisphere_pdf, random_cosine_hemisphere};
+use crate::sampler::Sampler;
+use crate::vector3::Vector3;
+use std::f64::consts::PI;
+
+// the reflectance of the clearcoat layer when looking straight at it (a
+// refraction index of 1.5), scaled down like in Disney's model so it stays subtle
+const CLEARCOAT_REFLECTANCE: f64 = 0.04;
+const CLEARCOAT_WEIGHT: f64 = 0.25;
+
+/// A Disney/glTF style metallic/roughness material made of a diffuse base,
+/// a GGX specular layer and an optional GGX clearcoat layer on top
+pub struct Principled {
+    base_color: Vector3,
+    metallic: f64,
+    alpha: f64,
+    specular: f64,
+    clearcoat: f64,
+    clearcoat_alpha: f64,
+}
+
+impl Principled {
+    /// Creates a new principled material. Every parameter other than
+    /// the base color goes from 0 to 1, and a `specular` of 0.5 gives
+    /// the 4% reflectance of most non-metals
+    pub fn new(
+        base_color: Vector3,
+        metallic: f64,
+        roughness: f64,
+        specular: f64,
+        clearcoat: f64,
+        clearcoat_roughness: f64,
+    ) -> Principled {
+        Principled {
+            base_color,
+            metallic: metallic.clamp(0.0, 1.0),
+            alpha: roughness_to_alpha(roughness.clamp(0.0, 1.0)),
+            specular: specular.clamp(0.0, 1.0),
+            clearcoat: clearcoat.clamp(0.0, 1.0),
+            clearcoat_alpha: roughness_to_alpha(clearcoat_roughness.clamp(0.0, 1.0)),
+        }
+    }
+
+    /// The specular reflectance when looking straight at the surface, which
+    /// is tinted by the base color for metals and grey for everything else
+    fn normal_reflectance(&self) -> Vector3 {
+        let dielectric = 0.08 * self.specular;
+        Vector3::new(dielectric, dielectric, dielectric).lerp(&self.base_color, self.metallic)
+    }
+
+    /// The chances of sampling the diffuse, specular and clearcoat layers
+    fn lobe_probabilities(&self) -> (f64, f64, f64) {
+        let diffuse = 1.0 - self.metallic;
+        let specular = 1.0;
+        let clearcoat = CLEARCOAT_WEIGHT * self.clearcoat;
+        let total = diffuse + specular + clearcoat;
+
+        (diffuse / total, specular / total, clearcoat / total)
+    }
+}
+
+impl Material for Principled {
+    fn sample(
+        &self,
+        hit: &Hit,
+        incoming: &Vector3,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterSample> {
+        let normal = hit.facing_normal();
+        let (diffuse, specular, _) = self.lobe_probabilities();
+
+        // pick a layer and sample a direction from it
+        let choice = sampler.get_1d();
+        let direction = if choice < diffuse {
+            random_cosine_hemisphere(&normal, sampler)
+        } else {
+            let alpha = if choice < diffuse + specular {
+                self.alpha
+            } else {
+                self.clearcoat_alpha
+            };
+
+            let half = sample_ggx_half_vector(&normal, alpha, sampler.get_2d());
+            incoming.reflect_across(&half)
+        };
+
+        // rays sent into the surface are absorbed
+        if direction.dot(&normal) <= 0.0 {
+            return None;
+        }
+
+        Some(ScatterSample {
+            direction,
+            value: self.evaluate(hit, incoming, &direction),
+            pdf: self.pdf(hit, incoming, &direction),
+            specular: false,
+        })
+    }
+
+    fn evaluate(&self, hit: &Hit, incoming: &Vector3, outgoing: &Vector3) -> Vector3 {
+        let normal = hit.facing_normal();
+        let view = -*incoming;
+        if outgoing.dot(&normal) <= 0.0 || view.dot(&normal) <= 0.0 {
+            return Vector3::default();
+        }
+
+        // light reflected by a layer on either the way in or the way out never
+        // reaches the layers below it (and metals have no diffuse base at all)
+        let cos_view = view.dot(&normal);
+        let cos_out = outgoing.dot(&normal);
+        let transmitted = |reflectance: f64| {
+            let reflectance = Vector3::new(1.0, 1.0, 1.0) * reflectance;
+            (1.0 - schlick_fresnel(cos_view, reflectance)[0])
+                * (1.0 - schlick_fresnel(cos_out, reflectance)[0])
+        };
+
+        let diffuse =
+            self.base_color * ((1.0 - self.metallic) * transmitted(0.08 * self.specular) / PI);
+        let specular = ggx_reflection(
+            &normal,
+            &view,
+            outgoing,
+            self.alpha,
+            self.normal_reflectance(),
+        );
+
+        let mut value = diffuse + specular;
+        if self.clearcoat > 0.0 {
+            let weight = CLEARCOAT_WEIGHT * self.clearcoat;
+            let reflectance = Vector3::new(1.0, 1.0, 1.0) * CLEARCOAT_REFLECTANCE;
+            let clearcoat =
+                ggx_reflection(&normal, &view, outgoing, self.clearcoat_alpha, reflectance);
+
+            let base_weight = 1.0 - weight * (1.0 - transmitted(CLEARCOAT_REFLECTANCE));
+            value = value * base_weight + clearcoat * weight;
+        }
+
+        value
+    }
+
+    fn pdf(&self, hit: &Hit, incoming: &Vector3, outgoing: &Vector3) -> f64 {
+        let normal = hit.facing_normal();
+        let view = -*incoming;
+        let (diffuse, specular, clearcoat) = self.lobe_probabilities();
+
+        // the direction could have come from any of the layers
+        let mut pdf = diffuse * cosine_hemisphere_pdf(outgoing.dot(&normal))
+            + specular * ggx_reflection_pdf(&normal, &view, outgoing, self.alpha);
+        if clearcoat > 0.0 {
+            pdf += clearcoat * ggx_reflection_pdf(&normal, &view, outgoing, self.clearcoat_alpha);
+        }
+
+        pdf
+    }
+}
@@ -1,19 +1,111 @@
+use crate::sampler::Sampler;
 use crate::vector3::Vector3;
-use rand::prelude::ThreadRng;
-use rand::Rng;
 use std::f64::consts::PI;
 
-pub fn random_normal_distribution(rng: &mut ThreadRng) -> f64 {
-    let theta = 2.0 * PI * rng.gen::<f64>();
-    let rho = (-2.0 * rng.gen::<f64>().ln()).sqrt();
-    rho * theta.cos()
+/// Returns a random direction in the hemisphere around `normal`, where
+/// directions are more likely the closer they are to the normal (the pdf
+/// is `cosine_hemisphere_pdf`). Uses Malley's method of projecting a
+/// uniform point on the disk up onto the hemisphere
+pub fn random_cosine_hemisphere(normal: &Vector3, sampler: &mut dyn Sampler) -> Vector3 {
+    let disk = random_in_unit_disk(sampler);
+    let height = (1.0 - disk.length_squared()).max(0.0).sqrt();
+
+    let (tangent, bitangent) = normal.orthonormal_basis();
+    tangent * disk.x() + bitangent * disk.y() + *normal * height
+}
+
+/// The probability density of `random_cosine_hemisphere` picking a
+/// direction, given the cosine of its angle with the normal
+pub fn cosine_hemisphere_pdf(cos_theta: f64) -> f64 {
+    cos_theta.max(0.0) / PI
+}
+
+/// Returns a uniformly random point inside the unit disk (z is always 0)
+pub fn random_in_unit_disk(sampler: &mut dyn Sampler) -> Vector3 {
+    // the square root keeps points from bunching up in the middle
+    let (u1, u2) = sampler.get_2d();
+    let radius = u1.sqrt();
+    let theta = 2.0 * PI * u2;
+    Vector3::new(radius * theta.cos(), radius * theta.sin(), 0.0)
+}
+
+/// Returns a uniformly random point inside a regular polygon inscribed in the
+/// unit circle, with its first corner `rotation` radians from +x (z is always 0)
+pub fn random_in_regular_polygon(sides: u32, rotation: f64, sampler: &mut dyn Sampler) -> Vector3 {
+    // pick one of the equally sized triangles fanning out from the center
+    let triangle = sampler.choose(sides as usize) as f64;
+    let corner_angle = 2.0 * PI / sides as f64;
+
+    let first_angle = rotation + triangle * corner_angle;
+    let second_angle = first_angle + corner_angle;
+    let first_corner = Vector3::new(first_angle.cos(), first_angle.sin(), 0.0);
+    let second_corner = Vector3::new(second_angle.cos(), second_angle.sin(), 0.0);
+
+    // pick a uniform point in that triangle
+    let (u1, u2) = sampler.get_2d();
+    let distance = u1.sqrt();
+    let across = u2;
+    distance * first_corner.lerp(&second_corner, across)
 }
 
-pub fn random_unit_vector(rng: &mut ThreadRng) -> Vector3 {
-    Vector3::new(
-        random_normal_distribution(rng),
-        random_normal_distribution(rng),
-        random_normal_distribution(rng),
-    )
-    .normalized()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+
+    // how many rings of equal width in cos(theta) the hemisphere is split into
+    const RING_COUNT: usize = 10;
+
+    #[test]
+    fn cosine_hemisphere_pdf_integrates_to_one() {
+        // integrate over cos(theta) in thin rings (the solid angle of
+        // each ring is 2 pi times its width in cos(theta))
+        let steps = 100000;
+        let integral: f64 = (0..steps)
+            .map(|step| {
+                let cos_theta = (step as f64 + 0.5) / steps as f64;
+                cosine_hemisphere_pdf(cos_theta) * 2.0 * PI / steps as f64
+            })
+            .sum();
+
+        assert!((integral - 1.0).abs() < 1e-6);
+        assert_eq!(cosine_hemisphere_pdf(-0.5), 0.0);
+    }
+
+    #[test]
+    fn random_cosine_hemisphere_matches_its_pdf() {
+        let normal = Vector3::new(1.0, 2.0, -0.5).normalized();
+        let mut sampler = IndependentSampler::new(5);
+        let samples = 200000;
+        let mut histogram = [0usize; RING_COUNT];
+
+        for index in 0..samples {
+            sampler.start_sample(0, 0, index);
+            let direction = random_cosine_hemisphere(&normal, &mut sampler);
+            assert!((direction.length() - 1.0).abs() < 1e-9);
+
+            let cos_theta = direction.dot(&normal);
+            assert!(cos_theta >= 0.0);
+            let ring = ((cos_theta * RING_COUNT as f64) as usize).min(RING_COUNT - 1);
+            histogram[ring] += 1;
+        }
+
+        for (ring, &count) in histogram.iter().enumerate() {
+            // the chance of landing in a ring is the pdf integrated over it
+            let (low, high) = (
+                ring as f64 / RING_COUNT as f64,
+                (ring + 1) as f64 / RING_COUNT as f64,
+            );
+            let expected = PI * (high * high - low * low) * cosine_hemisphere_pdf(1.0);
+            let found = count as f64 / samples as f64;
+
+            assert!(
+                (found - expected).abs() < 0.005,
+                "ring {} was picked {} of the time instead of {}",
+                ring,
+                found,
+                expected
+            );
+        }
+    }
 }
@@ -1,9 +1,10 @@
-use crate::constants::{LOWER_SKY_COLOR, MAX_BOUNCES, UPPER_SKY_COLOR};
+use crate::environment::Environment;
 use crate::hit::Hit;
+use crate::lights::LightSample;
 use crate::objects::Object;
-use crate::random::random_unit_vector;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
 use crate::vector3::Vector3;
-use rand::rngs::ThreadRng;
 
 /// A simple ray representing a ray of light
 #[derive(Copy, Clone)]
@@ -37,13 +38,12 @@ impl Ray {
     }
 
     /// Gets the environment light of a ray
-    pub fn get_environment_light(self) -> Vector3 {
-        let lerp_amount: f64 = (self.direction.y() + 1.0) * 0.5;
-        LOWER_SKY_COLOR.lerp(&UPPER_SKY_COLOR, lerp_amount)
+    pub fn get_environment_light(self, environment: &dyn Environment) -> Vector3 {
+        environment.radiance(&self.direction)
     }
 
     /// Returns the closest valid hit for this ray
-    pub fn get_hit(&self, objects: &[&dyn Object]) -> Option<Hit> {
+    pub fn get_hit<'a>(&self, objects: &'a [Box<dyn Object + Send + Sync>]) -> Option<Hit<'a>> {
         // keeps track of the closest hit to the ray
         let mut closest_hit: Option<Hit> = None;
 
@@ -72,45 +72,242 @@ impl Ray {
         closest_hit
     }
 
-    /// Traces a vector and returns the calculated color
-    pub fn trace(self, objects: &[&dyn Object], rng: &mut ThreadRng) -> Vector3 {
-        // variables to collect color and light of the ray
-        let mut color = Vector3::new(1.0, 1.0, 1.0);
+    /// Traces a vector and returns the calculated color. At every bounce, light
+    /// is sampled directly (next event estimation) as well as found by the
+    /// bounce itself, with the two combined by multiple importance sampling
+    pub fn trace(self, scene: &Scene, sampler: &mut dyn Sampler, stats: &mut PathStats) -> Vector3 {
+        // how much of the light found along the path still reaches the camera
+        let mut throughput = Vector3::new(1.0, 1.0, 1.0);
         let mut light = Vector3::default();
 
         // stores the current ray
         let mut ray = self;
 
-        for _ in 0..MAX_BOUNCES {
-            let optional_hit = ray.get_hit(objects);
+        // the pdf of the bounce that made the current ray, or None if it
+        // couldn't have been made by sampling a light (the camera ray and
+        // specular bounces)
+        let mut bounce_pdf: Option<f64> = None;
 
-            match optional_hit {
+        let settings = &scene.settings;
+        let mut bounces = 0;
+
+        while bounces < settings.max_bounces {
+            let hit = match scene.objects.get_hit(&ray) {
                 None => {
-                    light += ray.get_environment_light() * color;
+                    let environment = &scene.environment;
+                    let weight = match bounce_pdf {
+                        None => 1.0,
+                        Some(pdf) => power_heuristic(pdf, environment.pdf(&ray.direction)),
+                    };
+                    light += ray.get_environment_light(environment.as_ref()) * throughput * weight;
                     break;
                 }
+                Some(hit) => hit,
+            };
 
-                Some(hit) => {
-                    // calculate diffuse direction
-                    let diffuse_direction = random_unit_vector(rng) + hit.normal;
+            // weigh light found by bouncing against the chance
+            // of having found it by sampling the light instead
+            let emitted = hit.material.emitted(&hit);
+            if emitted.length_squared() > 0.0 {
+                let weight = match bounce_pdf {
+                    None => 1.0,
+                    Some(pdf) => power_heuristic(pdf, scene.lights.pdf(&ray.origin, &hit)),
+                };
+                light += emitted * throughput * weight;
+            }
 
-                    // calculate reflect direction
-                    let reflect_direction = ray.direction.reflect_across(&hit.normal);
+            bounces += 1;
 
-                    // account for smoothness
-                    let new_ray_direction =
-                        diffuse_direction.lerp(&reflect_direction, hit.material.smoothness);
+            // light found at the next hit is only counted if there is a next hit
+            if bounces < settings.max_bounces {
+                let light_sample = scene.lights.sample(&hit.point, sampler);
+                let environment_sample = scene.environment.sample(sampler);
 
-                    ray = Ray::new(hit.point, new_ray_direction);
+                for sample in [light_sample, environment_sample].into_iter().flatten() {
+                    light += direct_light(scene, &hit, &ray.direction, &sample) * throughput;
+                }
+            }
+
+            // let the material choose where the ray goes next (if anywhere)
+            let sample = match hit.material.sample(&hit, &ray.direction, sampler) {
+                None => break,
+                Some(sample) => sample,
+            };
+
+            // the estimator f * |cos| / pdf keeps the result unbiased
+            // no matter how the material picked the direction
+            match sample.weight(&hit.normal) {
+                None => break,
+                Some(weight) => throughput *= weight,
+            }
 
-                    let material = hit.material;
-                    let emitted_light = material.emission_color * material.emission_strength;
-                    light += emitted_light * color;
-                    color *= material.color;
+            // randomly end paths that can't carry much more light, boosting
+            // the ones that survive so the result stays unbiased
+            if bounces >= settings.min_bounces {
+                let survival = throughput[0].max(throughput[1]).max(throughput[2]).min(1.0);
+                if sampler.get_1d() >= survival {
+                    break;
                 }
+                throughput /= survival;
             }
+
+            bounce_pdf = (!sample.specular).then_some(sample.pdf);
+            ray = Ray::new(hit.point, sample.direction);
         }
 
+        stats.record(bounces);
         light
     }
 }
+
+/// Keeps track of how many times paths bounced
+#[derive(Copy, Clone, Default)]
+pub struct PathStats {
+    pub paths: u64,
+    pub bounces: u64,
+}
+
+impl PathStats {
+    /// Counts a finished path
+    pub fn record(&mut self, bounces: u32) {
+        self.paths += 1;
+        self.bounces += bounces as u64;
+    }
+
+    /// Adds the paths counted by another set of stats
+    pub fn merge(&mut self, other: &PathStats) {
+        self.paths += other.paths;
+        self.bounces += other.bounces;
+    }
+
+    /// Returns the average number of bounces per path
+    pub fn average_length(&self) -> f64 {
+        if self.paths == 0 {
+            return 0.0;
+        }
+
+        self.bounces as f64 / self.paths as f64
+    }
+}
+
+/// Weighs a sample from one strategy against another strategy
+/// that could've made the same sample (with a power of 2)
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let squared = pdf * pdf;
+    let other_squared = other_pdf * other_pdf;
+    if squared + other_squared == 0.0 {
+        return 0.0;
+    }
+
+    squared / (squared + other_squared)
+}
+
+/// Returns the light a hit reflects back along the incoming ray
+/// from a light sample (if the light isn't blocked)
+fn direct_light(scene: &Scene, hit: &Hit, incoming: &Vector3, sample: &LightSample) -> Vector3 {
+    if sample.pdf <= 0.0 {
+        return Vector3::default();
+    }
+
+    // specular materials (or lights behind the surface) reflect nothing
+    let reflectance = hit.material.evaluate(hit, incoming, &sample.direction);
+    if reflectance.length_squared() == 0.0 {
+        return Vector3::default();
+    }
+
+    // check for anything between the hit and the light
+    let shadow_ray = Ray::new(hit.point, sample.direction);
+    if let Some(blocker) = scene.objects.get_hit(&shadow_ray) {
+        if blocker.distance < sample.distance * (1.0 - 1e-6) {
+            return Vector3::default();
+        }
+    }
+
+    // lights a bounce couldn't have found don't need to be weighed
+    let weight = if sample.can_be_hit {
+        power_heuristic(
+            sample.pdf,
+            hit.material.pdf(hit, incoming, &sample.direction),
+        )
+    } else {
+        1.0
+    };
+    let cos_theta = sample.direction.dot(&hit.normal).abs();
+
+    reflectance * sample.radiance * (cos_theta * weight / sample.pdf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::random_in_unit_disk;
+    use crate::sampler::IndependentSampler;
+    use crate::scene::{SceneDescription, SceneFormat};
+
+    /// Builds a furnace scene: a single sphere made of `material` (written
+    /// as TOML) inside an environment that is white in every direction
+    fn furnace(material: &str) -> Scene {
+        let contents = format!(
+            r#"
+            [environment]
+            type = "gradient"
+            lower_color = [1.0, 1.0, 1.0]
+            upper_color = [1.0, 1.0, 1.0]
+
+            [[spheres]]
+            center = [0.0, 0.0, 0.0]
+            radius = 1.0
+            material = {}
+            "#,
+            material
+        );
+
+        SceneDescription::parse(&contents, SceneFormat::Toml)
+            .unwrap()
+            .build()
+            .unwrap()
+    }
+
+    /// Returns the average light traced along random rays that all hit the sphere
+    fn average_light(scene: &Scene, samples: u32) -> Vector3 {
+        let mut sampler = IndependentSampler::new(7);
+        let mut stats = PathStats::default();
+        let mut total = Vector3::default();
+
+        for index in 0..samples {
+            sampler.start_sample(0, 0, index);
+
+            // aim from far away at a random point on the sphere's disk
+            let disk = random_in_unit_disk(&mut sampler) * 0.99;
+            let origin = Vector3::new(disk.x(), disk.y(), 10.0);
+            let ray = Ray::new(origin, Vector3::new(0.0, 0.0, -1.0));
+            total += ray.trace(scene, &mut sampler, &mut stats);
+        }
+
+        total / samples as f64
+    }
+
+    /// Checks that every channel of `light` is within `tolerance` of `expected`
+    fn assert_close(light: Vector3, expected: f64, tolerance: f64) {
+        for channel in 0..3 {
+            assert!(
+                (light[channel] - expected).abs() < tolerance,
+                "expected {} but got {}",
+                expected,
+                light[channel]
+            );
+        }
+    }
+
+    #[test]
+    fn lambertian_furnace_reflects_its_albedo() {
+        let scene = furnace(r#"{ type = "lambertian", color = [0.5, 0.5, 0.5] }"#);
+        assert_close(average_light(&scene, 20000), 0.5, 0.01);
+    }
+
+    #[test]
+    fn dielectric_furnace_loses_no_light() {
+        let scene = furnace(r#"{ type = "dielectric" }"#);
+        assert_close(average_light(&scene, 20000), 1.0, 0.01);
+    }
+}
@@ -0,0 +1,183 @@
+use crate::film::{Pixel, SplatRegion};
+use crate::ray::PathStats;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use progress_bar::inc_progress_bar;
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::sync::Mutex;
+use std::thread;
+
+// the width and height of the blocks of pixels handed out to threads
+const TILE_SIZE: u32 = 32;
+
+/// A square block of pixels (cut short at the right and bottom edges of the image)
+#[derive(Copy, Clone)]
+struct Tile {
+    index: usize,
+    x: u32,
+    row: u32,
+    width: u32,
+    height: u32,
+}
+
+/// Splits an image into tiles, row by row from the top left
+fn split_into_tiles(width: u32, height: u32) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+    for row in (0..height).step_by(TILE_SIZE as usize) {
+        for x in (0..width).step_by(TILE_SIZE as usize) {
+            tiles.push(Tile {
+                index: tiles.len(),
+                x,
+                row,
+                width: TILE_SIZE.min(width - x),
+                height: TILE_SIZE.min(height - row),
+            });
+        }
+    }
+    tiles
+}
+
+/// Returns how many tiles each pass over an image is split into
+pub fn tile_count(width: u32, height: u32) -> usize {
+    let across = width.div_ceil(TILE_SIZE) as usize;
+    let down = height.div_ceil(TILE_SIZE) as usize;
+    across * down
+}
+
+/// Takes the next tile for a thread from the front of its own queue, or steals
+/// one from the back of another thread's queue once its own is empty
+fn next_tile(queues: &[Mutex<VecDeque<Tile>>], worker: usize) -> Option<Tile> {
+    if let Some(tile) = queues[worker].lock().unwrap().pop_front() {
+        return Some(tile);
+    }
+
+    (1..queues.len())
+        .map(|offset| (worker + offset) % queues.len())
+        .find_map(|victim| queues[victim].lock().unwrap().pop_back())
+}
+
+/// Renders one pass over the image on `threads` threads, where `samples` is which
+/// samples each pixel takes (row by row from the top of the image), returning
+/// the new samples of each pixel and the regions of each tile (in order) they were
+/// splatted onto so they can be added to the film
+pub fn render_pass(
+    scene: &Scene,
+    samples: &[Range<u32>],
+    threads: usize,
+) -> (Vec<Pixel>, Vec<SplatRegion>, PathStats) {
+    let settings = scene.settings;
+    let tiles = split_into_tiles(settings.image_width, settings.image_height);
+
+    // give each thread its own run of neighboring tiles to start with
+    let queues: Vec<Mutex<VecDeque<Tile>>> = (0..threads)
+        .map(|worker| {
+            let start = worker * tiles.len() / threads;
+            let end = (worker + 1) * tiles.len() / threads;
+            Mutex::new(tiles[start..end].iter().copied().collect())
+        })
+        .collect();
+
+    let pixel_count = settings.image_width as usize * settings.image_height as usize;
+    let pixels = Mutex::new(vec![Pixel::default(); pixel_count]);
+    let regions: Mutex<Vec<Option<SplatRegion>>> =
+        Mutex::new((0..tiles.len()).map(|_| None).collect());
+    let stats = Mutex::new(PathStats::default());
+
+    thread::scope(|scope| {
+        for worker in 0..threads {
+            let (queues, pixels, regions, stats) = (&queues, &pixels, &regions, &stats);
+
+            scope.spawn(move || {
+                let mut sampler = settings
+                    .sampler
+                    .create(settings.rays_per_pixel, settings.seed);
+                let mut worker_stats = PathStats::default();
+
+                while let Some(tile) = next_tile(queues, worker) {
+                    let (tile_pixels, region) =
+                        render_tile(scene, &tile, samples, sampler.as_mut(), &mut worker_stats);
+                    regions.lock().unwrap()[tile.index] = Some(region);
+
+                    let mut pixels = pixels.lock().unwrap();
+                    for (index, pixel) in tile_pixels.into_iter().enumerate() {
+                        let x = tile.x + index as u32 % tile.width;
+                        let row = tile.row + index as u32 / tile.width;
+                        pixels[row as usize * settings.image_width as usize + x as usize] = pixel;
+                    }
+
+                    inc_progress_bar();
+                }
+
+                stats.lock().unwrap().merge(&worker_stats);
+            });
+        }
+    });
+
+    let regions = regions
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .flatten()
+        .collect();
+    (
+        pixels.into_inner().unwrap(),
+        regions,
+        stats.into_inner().unwrap(),
+    )
+}
+
+/// Renders the pixels of a tile (row by row from its top left), splatting
+/// their samples onto a region around the tile that the filter can reach
+fn render_tile(
+    scene: &Scene,
+    tile: &Tile,
+    samples: &[Range<u32>],
+    sampler: &mut dyn Sampler,
+    stats: &mut PathStats,
+) -> (Vec<Pixel>, SplatRegion) {
+    let settings = scene.settings;
+    let filter = settings.filter();
+
+    let margin = (filter.radius() + 0.5).ceil() as u32;
+    let mut region = SplatRegion::new(
+        tile.x as i64 - margin as i64,
+        tile.row as i64 - margin as i64,
+        tile.width + 2 * margin,
+        tile.height + 2 * margin,
+    );
+    let mut pixels = Vec::with_capacity((tile.width * tile.height) as usize);
+
+    for row in tile.row..tile.row + tile.height {
+        // the camera counts pixels up from the bottom of the image
+        let pixel_y = settings.image_height - 1 - row;
+
+        for pixel_x in tile.x..tile.x + tile.width {
+            let mut pixel = Pixel::default();
+            let pixel_samples =
+                &samples[row as usize * settings.image_width as usize + pixel_x as usize];
+
+            for index in pixel_samples.clone() {
+                sampler.start_sample(pixel_x, pixel_y, index);
+
+                // pick a point inside the pixel (in pixels from the top left of the image)
+                let (offset_x, offset_y) = sampler.get_2d();
+                let x = pixel_x as f64 + offset_x;
+                let y = row as f64 + offset_y;
+
+                let width_ratio = x / settings.image_width as f64;
+                let height_ratio = 1.0 - y / settings.image_height as f64;
+
+                let ray = scene.camera.get_ray(width_ratio, height_ratio, sampler);
+                let color = ray.trace(scene, sampler, stats);
+
+                pixel.add_sample(color);
+                region.splat(&filter, x, y, color);
+            }
+
+            pixels.push(pixel);
+        }
+    }
+
+    (pixels, region)
+}
@@ -0,0 +1,443 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+// how many dimensions the Halton sampler has prime bases for
+// (later dimensions fall back to hashed random values)
+const HALTON_DIMENSIONS: usize = 256;
+
+/// Produces the random numbers used to render a sample of a pixel. Each call takes
+/// the next dimension of the sample, so that samplers can spread the values of each
+/// dimension more evenly over the samples of a pixel than independent random numbers
+pub trait Sampler {
+    /// Starts over from the first dimension for the `index`th sample of a pixel
+    fn start_sample(&mut self, pixel_x: u32, pixel_y: u32, index: u32);
+
+    /// Returns the next dimension of the sample, from 0 to 1 (not including 1)
+    fn get_1d(&mut self) -> f64;
+
+    /// Returns the next two dimensions of the sample, from 0 to 1 (not including 1)
+    fn get_2d(&mut self) -> (f64, f64);
+
+    /// Picks one of `count` choices from the next dimension of the sample
+    fn choose(&mut self, count: usize) -> usize {
+        ((self.get_1d() * count as f64) as usize).min(count - 1)
+    }
+}
+
+/// The kinds of samplers a render can use
+#[derive(Copy, Clone, Default, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplerKind {
+    /// Independent uniform random numbers
+    Independent,
+    /// Randomly jittered points inside a shuffled grid of strata
+    Stratified,
+    /// The Halton sequence, randomly shifted for each pixel
+    Halton,
+    /// The Sobol sequence with Owen scrambling
+    #[default]
+    Sobol,
+}
+
+impl SamplerKind {
+    /// Creates a sampler of this kind for pixels that take `samples_per_pixel` samples,
+    /// where the same `seed` always gives the same numbers for the same samples
+    pub fn create(self, samples_per_pixel: u32, seed: u64) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+        }
+    }
+}
+
+/// A small and fast random number generator (O'Neill's PCG32), which gives
+/// the same numbers every time it starts from the same seed and stream
+struct Pcg32 {
+    state: u64,
+    increment: u64,
+}
+
+impl Pcg32 {
+    /// Creates a new generator, where each stream is a different sequence of numbers
+    fn new(seed: u64, stream: u64) -> Pcg32 {
+        let mut rng = Pcg32 {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    /// Returns the next 32 random bits
+    fn next_u32(&mut self) -> u32 {
+        let state = self.state;
+        self.state = state
+            .wrapping_mul(0x5851_f42d_4c95_7f2d)
+            .wrapping_add(self.increment);
+
+        let xor_shifted = (((state >> 18) ^ state) >> 27) as u32;
+        xor_shifted.rotate_right((state >> 59) as u32)
+    }
+
+    /// Returns a random number from 0 to 1 (not including 1)
+    fn next_f64(&mut self) -> f64 {
+        bits_to_unit(self.next_u32())
+    }
+}
+
+/// Mixes the bits of a number (the finalizer of MurmurHash3)
+fn mix_bits(mut value: u32) -> u32 {
+    value ^= value >> 16;
+    value = value.wrapping_mul(0x85eb_ca6b);
+    value ^= value >> 13;
+    value = value.wrapping_mul(0xc2b2_ae35);
+    value ^ (value >> 16)
+}
+
+/// Hashes a list of numbers into a seemingly random number
+fn hash(values: &[u32]) -> u32 {
+    values.iter().fold(0x9e37_79b9, |hash, &value| {
+        mix_bits(hash ^ value.wrapping_mul(0x2c1b_3c6d))
+    })
+}
+
+/// Converts 32 random bits into a number from 0 to 1 (not including 1)
+fn bits_to_unit(bits: u32) -> f64 {
+    bits as f64 / 4_294_967_296.0
+}
+
+/// Returns where `index` ends up in a random permutation of 0 to
+/// `count` picked by `seed`, without building the permutation
+/// (Kensler's "Correlated Multi-Jittered Sampling")
+fn permutation_element(mut index: u32, count: u32, seed: u32) -> u32 {
+    // a mask covering every bit `count` could use
+    let mut mask = count - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+
+    // shuffle inside the power of two above `count` until landing inside it
+    loop {
+        index ^= seed;
+        index = index.wrapping_mul(0xe170_893d);
+        index ^= seed >> 16;
+        index ^= (index & mask) >> 4;
+        index ^= seed >> 8;
+        index = index.wrapping_mul(0x0929_eb3f);
+        index ^= seed >> 23;
+        index ^= (index & mask) >> 1;
+        index = index.wrapping_mul(1 | seed >> 27);
+        index = index.wrapping_mul(0x6935_fa69);
+        index ^= (index & mask) >> 11;
+        index = index.wrapping_mul(0x74dc_b303);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0x9e50_1cc3);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0xc860_a3df);
+        index &= mask;
+        index ^= index >> 5;
+
+        if index < count {
+            break;
+        }
+    }
+
+    ((index as u64 + seed as u64) % count as u64) as u32
+}
+
+/// Where a sampler is in the samples of a pixel, and the seed of the render
+#[derive(Copy, Clone)]
+struct SamplePosition {
+    render_seed: u64,
+    pixel_x: u32,
+    pixel_y: u32,
+    index: u32,
+    dimension: u32,
+}
+
+impl SamplePosition {
+    /// Creates a new position at the first sample of the first pixel
+    fn new(render_seed: u64) -> SamplePosition {
+        SamplePosition {
+            render_seed,
+            pixel_x: 0,
+            pixel_y: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// Moves to the first dimension of the `index`th sample of a pixel
+    fn start(&mut self, pixel_x: u32, pixel_y: u32, index: u32) {
+        self.pixel_x = pixel_x;
+        self.pixel_y = pixel_y;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    /// Returns a seed that's different for every pixel and dimension
+    fn seed(&self) -> u32 {
+        hash(&[
+            self.render_seed as u32,
+            (self.render_seed >> 32) as u32,
+            self.pixel_x,
+            self.pixel_y,
+            self.dimension,
+        ])
+    }
+
+    /// Returns a random number generator for the current sample, with a stream
+    /// for each pixel started from a different place for each sample
+    fn sample_rng(&self) -> Pcg32 {
+        let sample_seed = self
+            .render_seed
+            .wrapping_add((self.index as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        let pixel_stream = ((self.pixel_y as u64) << 32) | self.pixel_x as u64;
+        Pcg32::new(sample_seed, pixel_stream)
+    }
+
+    /// Moves on to the next `count` dimensions, returning the first of them
+    fn advance(&mut self, count: u32) -> u32 {
+        let dimension = self.dimension;
+        self.dimension += count;
+        dimension
+    }
+}
+
+/// Takes independent uniform random numbers for every dimension
+pub struct IndependentSampler {
+    rng: Pcg32,
+    position: SamplePosition,
+}
+
+impl IndependentSampler {
+    /// Creates a new independent sampler
+    pub fn new(seed: u64) -> IndependentSampler {
+        let position = SamplePosition::new(seed);
+        IndependentSampler {
+            rng: position.sample_rng(),
+            position,
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_sample(&mut self, pixel_x: u32, pixel_y: u32, index: u32) {
+        self.position.start(pixel_x, pixel_y, index);
+        self.rng = self.position.sample_rng();
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.rng.next_f64()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.rng.next_f64(), self.rng.next_f64())
+    }
+}
+
+/// Splits each dimension into as many strata as there are samples (or a square
+/// grid of strata for pairs of dimensions), giving each sample a random point in a
+/// different stratum. The strata are shuffled differently for every pixel and
+/// dimension so the dimensions aren't correlated with each other
+pub struct StratifiedSampler {
+    rng: Pcg32,
+    samples_per_pixel: u32,
+    grid_size: u32,
+    position: SamplePosition,
+}
+
+impl StratifiedSampler {
+    /// Creates a new stratified sampler for pixels that take `samples_per_pixel` samples
+    pub fn new(samples_per_pixel: u32, seed: u64) -> StratifiedSampler {
+        let samples_per_pixel = samples_per_pixel.max(1);
+        let position = SamplePosition::new(seed);
+        StratifiedSampler {
+            rng: position.sample_rng(),
+            samples_per_pixel,
+            grid_size: ((samples_per_pixel as f64).sqrt() as u32).max(1),
+            position,
+        }
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, pixel_x: u32, pixel_y: u32, index: u32) {
+        self.position.start(pixel_x, pixel_y, index);
+        self.rng = self.position.sample_rng();
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let seed = self.position.seed();
+        self.position.advance(1);
+
+        let count = self.samples_per_pixel;
+        let stratum = permutation_element(self.position.index % count, count, seed);
+        (stratum as f64 + self.rng.next_f64()) / count as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let seed = self.position.seed();
+        self.position.advance(2);
+
+        let size = self.grid_size;
+        let count = size * size;
+        let stratum = permutation_element(self.position.index % count, count, seed);
+        (
+            ((stratum % size) as f64 + self.rng.next_f64()) / size as f64,
+            ((stratum / size) as f64 + self.rng.next_f64()) / size as f64,
+        )
+    }
+}
+
+/// Uses the Halton sequence (the radical inverse of the sample's index in a
+/// different prime base for each dimension), shifted by a random amount for every
+/// pixel and dimension (a Cranley-Patterson rotation) so neighboring pixels
+/// don't share the same pattern
+pub struct HaltonSampler {
+    primes: Vec<u32>,
+    position: SamplePosition,
+}
+
+impl HaltonSampler {
+    /// Creates a new Halton sampler
+    pub fn new(seed: u64) -> HaltonSampler {
+        let mut primes: Vec<u32> = Vec::with_capacity(HALTON_DIMENSIONS);
+        let mut candidate = 2;
+        while primes.len() < HALTON_DIMENSIONS {
+            if primes.iter().all(|prime| candidate % prime != 0) {
+                primes.push(candidate);
+            }
+            candidate += 1;
+        }
+
+        HaltonSampler {
+            primes,
+            position: SamplePosition::new(seed),
+        }
+    }
+
+    /// Returns the next dimension of the sample
+    fn next(&mut self) -> f64 {
+        let seed = self.position.seed();
+        let dimension = self.position.advance(1) as usize;
+        let index = self.position.index;
+
+        let base = match self.primes.get(dimension) {
+            Some(&base) => base,
+            None => return bits_to_unit(hash(&[seed, index])),
+        };
+
+        // mirror the digits of the index around the decimal point
+        let mut remaining = index;
+        let mut inverse = 0.0;
+        let mut digit_scale = 1.0 / base as f64;
+        while remaining > 0 {
+            inverse += (remaining % base) as f64 * digit_scale;
+            remaining /= base;
+            digit_scale /= base as f64;
+        }
+
+        let shifted = inverse + bits_to_unit(seed);
+        if shifted >= 1.0 {
+            shifted - 1.0
+        } else {
+            shifted
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, pixel_x: u32, pixel_y: u32, index: u32) {
+        self.position.start(pixel_x, pixel_y, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.next()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.next(), self.next())
+    }
+}
+
+/// Randomly permutes the bits of a number like a tree of random flips, where
+/// each bit is flipped based on the bits above it (Burley's hash-based Owen
+/// scrambling, using Laine and Karras's hash on the reversed bits)
+fn owen_scramble(value: u32, seed: u32) -> u32 {
+    let mut value = value.reverse_bits();
+    value = value.wrapping_add(seed);
+    value ^= value.wrapping_mul(0x6c50_b47c);
+    value ^= value.wrapping_mul(0xb82f_1e52);
+    value ^= value.wrapping_mul(0xc7af_e638);
+    value ^= value.wrapping_mul(0x8d22_f6e6);
+    value.reverse_bits()
+}
+
+/// Returns the first two dimensions of the Sobol sequence as 32-bit fractions
+fn sobol_2d(index: u32) -> (u32, u32) {
+    // the first dimension is the van der Corput sequence, and the second's
+    // direction numbers each flip the bits of the last one shifted down
+    let mut second = 0;
+    let mut direction = 1 << 31;
+    let mut remaining = index;
+    while remaining != 0 {
+        if remaining & 1 != 0 {
+            second ^= direction;
+        }
+        remaining >>= 1;
+        direction ^= direction >> 1;
+    }
+
+    (index.reverse_bits(), second)
+}
+
+/// Uses the first two dimensions of the Sobol sequence for every pair of
+/// dimensions, with Owen scrambling that's different for every pixel and pair.
+/// The sample indices are scrambled as well, which keeps the pairs from being
+/// correlated with each other (Burley's "Practical Hash-based Owen Scrambling")
+pub struct SobolSampler {
+    position: SamplePosition,
+}
+
+impl SobolSampler {
+    /// Creates a new Sobol sampler
+    pub fn new(seed: u64) -> SobolSampler {
+        SobolSampler {
+            position: SamplePosition::new(seed),
+        }
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, pixel_x: u32, pixel_y: u32, index: u32) {
+        self.position.start(pixel_x, pixel_y, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let seed = self.position.seed();
+        self.position.advance(1);
+
+        let index = owen_scramble(self.position.index, seed);
+        let (value, _) = sobol_2d(index);
+        bits_to_unit(owen_scramble(value, mix_bits(seed)))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let seed = self.position.seed();
+        self.position.advance(2);
+
+        let index = owen_scramble(self.position.index, seed);
+        let (first, second) = sobol_2d(index);
+        (
+            bits_to_unit(owen_scramble(first, mix_bits(seed))),
+            bits_to_unit(owen_scramble(second, mix_bits(seed ^ 0x5bd1_e995))),
+        )
+    }
+}
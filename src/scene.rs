@@ -0,0 +1,1127 @@
+use crate::bvh::Bvh;
+use crate::camera::{ApertureShape, Camera, Lens};
+use crate::constants::{
+    DEFAULT_LOWER_SKY_COLOR, DEFAULT_REFRACTION_INDEX, DEFAULT_UPPER_SKY_COLOR,
+    DEFAULT_VERTICAL_FOV,
+};
+use crate::environment::{Environment, EnvironmentMap, Gradient};
+use crate::input::load_hdr_image;
+use crate::lights::{
+    AreaLight, AreaShape, DirectionalLight, Light, LightList, PointLight, SpotLight,
+};
+use crate::material::{Dielectric, Emissive, Lambertian, Material, Metal, Mirror};
+use crate::mesh::{Mesh, MeshFace};
+use crate::obj::ObjModel;
+use crate::objects::Object;
+use crate::principled::Principled;
+use crate::settings::RenderSettings;
+use crate::sky::{sun_direction, PreethamSky};
+use crate::sphere::Sphere;
+use crate::triangle::Triangle;
+use crate::vector3::Vector3;
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The file formats a scene description can be stored in
+#[derive(Copy, Clone)]
+pub enum SceneFormat {
+    Toml,
+    Json,
+}
+
+impl SceneFormat {
+    /// Picks a format based on the extension of a path
+    pub fn from_path(path: &Path) -> anyhow::Result<SceneFormat> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Ok(SceneFormat::Toml),
+            Some("json") => Ok(SceneFormat::Json),
+            _ => bail!(
+                "Scene file `{}` must have a `.toml` or `.json` extension",
+                path.display()
+            ),
+        }
+    }
+}
+
+/// Describes a material in a scene file, picked by its `type`
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum MaterialDescription {
+    Lambertian {
+        color: Vector3,
+    },
+    Mirror {
+        #[serde(default = "default_white")]
+        color: Vector3,
+    },
+    Metal {
+        color: Vector3,
+        roughness: f64,
+    },
+    Principled {
+        base_color: Vector3,
+        #[serde(default)]
+        metallic: f64,
+        #[serde(default = "default_roughness")]
+        roughness: f64,
+        #[serde(default = "default_specular")]
+        specular: f64,
+        #[serde(default)]
+        clearcoat: f64,
+        #[serde(default = "default_clearcoat_roughness")]
+        clearcoat_roughness: f64,
+    },
+    Dielectric {
+        #[serde(default = "default_white")]
+        color: Vector3,
+        #[serde(default = "default_refraction_index")]
+        refraction_index: f64,
+    },
+    Emissive {
+        color: Vector3,
+        #[serde(default = "default_emission_strength")]
+        strength: f64,
+    },
+}
+
+fn default_white() -> Vector3 {
+    Vector3::new(1.0, 1.0, 1.0)
+}
+
+fn default_roughness() -> f64 {
+    0.5
+}
+
+fn default_specular() -> f64 {
+    0.5
+}
+
+fn default_clearcoat_roughness() -> f64 {
+    0.03
+}
+
+fn default_refraction_index() -> f64 {
+    DEFAULT_REFRACTION_INDEX
+}
+
+fn default_emission_strength() -> f64 {
+    1.0
+}
+
+impl MaterialDescription {
+    /// Creates the Material this description represents
+    pub fn build(&self) -> anyhow::Result<Arc<dyn Material>> {
+        let material: Arc<dyn Material> = match *self {
+            MaterialDescription::Lambertian { color } => Arc::new(Lambertian::new(color)),
+            MaterialDescription::Mirror { color } => Arc::new(Mirror::new(color)),
+            MaterialDescription::Metal { color, roughness } => {
+                Arc::new(Metal::new(color, roughness))
+            }
+            MaterialDescription::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                clearcoat,
+                clearcoat_roughness,
+            } => Arc::new(Principled::new(
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                clearcoat,
+                clearcoat_roughness,
+            )),
+            MaterialDescription::Dielectric {
+                color,
+                refraction_index,
+            } => Arc::new(Dielectric::new(color, refraction_index)?),
+            MaterialDescription::Emissive { color, strength } => {
+                Arc::new(Emissive::new(color, strength))
+            }
+        };
+
+        Ok(material)
+    }
+}
+
+/// Either the name of a material from the scene's material
+/// table or a material written out in place
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MaterialReference {
+    Named(String),
+    Inline(MaterialDescription),
+}
+
+/// Describes a sphere in a scene file
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SphereDescription {
+    pub center: Vector3,
+    pub radius: f64,
+    pub material: MaterialReference,
+}
+
+/// Describes a triangle in a scene file (vertices are counter-clockwise
+/// seen from outside, and normals are optional for smooth shading)
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TriangleDescription {
+    pub vertices: [Vector3; 3],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normals: Option<[Vector3; 3]>,
+    pub material: MaterialReference,
+}
+
+/// Describes a triangle mesh in a scene file (if there are
+/// normals, there must be exactly one for every vertex)
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MeshDescription {
+    pub vertices: Vec<Vector3>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub normals: Vec<Vector3>,
+    pub faces: Vec<[usize; 3]>,
+    pub material: MaterialReference,
+}
+
+/// Describes a Wavefront OBJ model in a scene file (the path is
+/// relative to the scene file, and faces without an MTL material
+/// use `material`). If `groups` isn't empty, only faces from those
+/// groups are loaded
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelDescription {
+    pub path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material: Option<MaterialReference>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+    #[serde(default = "default_model_scale")]
+    pub scale: f64,
+    #[serde(default = "Vector3::default")]
+    pub offset: Vector3,
+}
+
+fn default_model_scale() -> f64 {
+    1.0
+}
+
+impl ModelDescription {
+    /// Describes an OBJ model loaded as-is
+    pub fn new(path: PathBuf) -> ModelDescription {
+        ModelDescription {
+            path,
+            material: None,
+            groups: Vec::new(),
+            scale: default_model_scale(),
+            offset: Vector3::default(),
+        }
+    }
+}
+
+/// Describes a light in a scene file, picked by its `type`. Point-like
+/// lights (point, spot and directional) are never seen by rays, while
+/// area lights (rect and disk) are seen unless `visible` is false. For
+/// directional and spot lights, `direction` is the way the light travels
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum LightDescription {
+    Point {
+        position: Vector3,
+        #[serde(default = "default_white")]
+        color: Vector3,
+        intensity: f64,
+    },
+    Spot {
+        position: Vector3,
+        direction: Vector3,
+        #[serde(default = "default_white")]
+        color: Vector3,
+        intensity: f64,
+        #[serde(default = "default_spot_inner_angle")]
+        inner_angle: f64,
+        #[serde(default = "default_spot_outer_angle")]
+        outer_angle: f64,
+    },
+    Directional {
+        direction: Vector3,
+        #[serde(default = "default_white")]
+        color: Vector3,
+        intensity: f64,
+    },
+    Rect {
+        corner: Vector3,
+        edge_1: Vector3,
+        edge_2: Vector3,
+        #[serde(default = "default_white")]
+        color: Vector3,
+        intensity: f64,
+        #[serde(default = "default_visible")]
+        visible: bool,
+    },
+    Disk {
+        center: Vector3,
+        normal: Vector3,
+        radius: f64,
+        #[serde(default = "default_white")]
+        color: Vector3,
+        intensity: f64,
+        #[serde(default = "default_visible")]
+        visible: bool,
+    },
+}
+
+type ObjectBox = Box<dyn Object + Send + Sync>;
+
+fn default_spot_inner_angle() -> f64 {
+    20.0
+}
+
+fn default_spot_outer_angle() -> f64 {
+    30.0
+}
+
+fn default_visible() -> bool {
+    true
+}
+
+impl LightDescription {
+    /// Creates the light this description represents, along with an object
+    /// to add to the scene if the light can be seen
+    pub fn build(&self) -> anyhow::Result<(Box<dyn Light>, Option<ObjectBox>)> {
+        let area_light = |shape: AreaShape, radiance: Vector3, visible: bool| {
+            let light = AreaLight::new(shape, radiance, visible);
+            let object = light
+                .is_visible()
+                .then(|| Box::new(light.clone()) as ObjectBox);
+            (Box::new(light) as Box<dyn Light>, object)
+        };
+
+        Ok(match *self {
+            LightDescription::Point {
+                position,
+                color,
+                intensity,
+            } => (Box::new(PointLight::new(position, color * intensity)), None),
+            LightDescription::Spot {
+                position,
+                direction,
+                color,
+                intensity,
+                inner_angle,
+                outer_angle,
+            } => {
+                if direction.length_squared() == 0.0 {
+                    bail!("Spot lights need a direction");
+                }
+                if !(0.0 <= inner_angle && inner_angle < outer_angle && outer_angle <= 180.0) {
+                    bail!(
+                        "Spot light angles must satisfy 0 <= inner_angle < outer_angle <= 180 (got {} and {})",
+                        inner_angle,
+                        outer_angle
+                    );
+                }
+
+                let light = SpotLight::new(
+                    position,
+                    direction,
+                    color * intensity,
+                    inner_angle,
+                    outer_angle,
+                );
+                (Box::new(light), None)
+            }
+            LightDescription::Directional {
+                direction,
+                color,
+                intensity,
+            } => {
+                if direction.length_squared() == 0.0 {
+                    bail!("Directional lights need a direction");
+                }
+
+                (
+                    Box::new(DirectionalLight::new(direction, color * intensity)),
+                    None,
+                )
+            }
+            LightDescription::Rect {
+                corner,
+                edge_1,
+                edge_2,
+                color,
+                intensity,
+                visible,
+            } => {
+                if edge_1.cross(&edge_2).length_squared() == 0.0 {
+                    bail!("Rect light edges can't be parallel or zero");
+                }
+
+                let shape = AreaShape::Rect {
+                    corner,
+                    edge_1,
+                    edge_2,
+                };
+                area_light(shape, color * intensity, visible)
+            }
+            LightDescription::Disk {
+                center,
+                normal,
+                radius,
+                color,
+                intensity,
+                visible,
+            } => {
+                if normal.length_squared() == 0.0 || radius <= 0.0 {
+                    bail!("Disk lights need a normal and a positive radius");
+                }
+
+                let shape = AreaShape::Disk {
+                    center,
+                    normal,
+                    radius,
+                };
+                area_light(shape, color * intensity, visible)
+            }
+        })
+    }
+}
+
+/// Describes the camera in a scene file (if `look_at` isn't given, the camera
+/// looks down +z from `look_from`, and if `focus_distance` isn't given, the
+/// camera focuses on `look_at`)
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraDescription {
+    #[serde(default = "Vector3::default", alias = "position")]
+    pub look_from: Vector3,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub look_at: Option<Vector3>,
+    #[serde(default = "default_camera_up")]
+    pub up: Vector3,
+    #[serde(default = "default_vertical_fov")]
+    pub vertical_fov: f64,
+    #[serde(default)]
+    pub aperture: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub focus_distance: Option<f64>,
+    #[serde(default)]
+    pub aperture_shape: ApertureShape,
+}
+
+fn default_camera_up() -> Vector3 {
+    Vector3::new(0.0, 1.0, 0.0)
+}
+
+fn default_vertical_fov() -> f64 {
+    DEFAULT_VERTICAL_FOV
+}
+
+impl Default for CameraDescription {
+    fn default() -> CameraDescription {
+        CameraDescription {
+            look_from: Vector3::default(),
+            look_at: None,
+            up: default_camera_up(),
+            vertical_fov: DEFAULT_VERTICAL_FOV,
+            aperture: 0.0,
+            focus_distance: None,
+            aperture_shape: ApertureShape::Disk,
+        }
+    }
+}
+
+/// Describes the environment surrounding a scene file, picked by its `type`:
+/// either a gradient between two colors, an equirectangular `.hdr` or `.pfm`
+/// image (relative to the scene file) spun around +y by `rotation` degrees
+/// with its colors multiplied by `intensity`, or a daylight sky with the sun
+/// `sun_elevation` degrees above the horizon and turned `sun_azimuth` degrees
+/// from +z towards +x. The sky adds a matching sun light unless `sun_intensity` is 0
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum EnvironmentDescription {
+    Gradient {
+        #[serde(default = "default_lower_sky_color")]
+        lower_color: Vector3,
+        #[serde(default = "default_upper_sky_color")]
+        upper_color: Vector3,
+    },
+    Map {
+        path: PathBuf,
+        #[serde(default)]
+        rotation: f64,
+        #[serde(default = "default_environment_intensity")]
+        intensity: f64,
+    },
+    Sky {
+        #[serde(default = "default_sun_elevation")]
+        sun_elevation: f64,
+        #[serde(default)]
+        sun_azimuth: f64,
+        #[serde(default = "default_turbidity")]
+        turbidity: f64,
+        #[serde(default = "default_environment_intensity")]
+        intensity: f64,
+        #[serde(default = "default_environment_intensity")]
+        sun_intensity: f64,
+    },
+}
+
+fn default_lower_sky_color() -> Vector3 {
+    DEFAULT_LOWER_SKY_COLOR
+}
+
+fn default_upper_sky_color() -> Vector3 {
+    DEFAULT_UPPER_SKY_COLOR
+}
+
+fn default_environment_intensity() -> f64 {
+    1.0
+}
+
+fn default_sun_elevation() -> f64 {
+    45.0
+}
+
+fn default_turbidity() -> f64 {
+    3.0
+}
+
+impl EnvironmentDescription {
+    /// Describes a daylight sky with the default settings
+    pub fn default_sky() -> EnvironmentDescription {
+        EnvironmentDescription::Sky {
+            sun_elevation: default_sun_elevation(),
+            sun_azimuth: 0.0,
+            turbidity: default_turbidity(),
+            intensity: 1.0,
+            sun_intensity: 1.0,
+        }
+    }
+}
+
+impl Default for EnvironmentDescription {
+    fn default() -> EnvironmentDescription {
+        EnvironmentDescription::Gradient {
+            lower_color: DEFAULT_LOWER_SKY_COLOR,
+            upper_color: DEFAULT_UPPER_SKY_COLOR,
+        }
+    }
+}
+
+// the hash 64-bit FNV-1a starts from
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Continues a 64-bit FNV-1a hash over some more bytes
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// A scene that has been built and is ready to be rendered
+pub struct Scene {
+    pub settings: RenderSettings,
+    pub camera: Camera,
+    pub environment: Box<dyn Environment>,
+    pub objects: Bvh,
+    pub lights: LightList,
+}
+
+/// A declarative description of everything in a scene
+/// that can be loaded from and saved to a file
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneDescription {
+    #[serde(default)]
+    pub render: RenderSettings,
+    #[serde(default)]
+    pub camera: CameraDescription,
+    #[serde(default)]
+    pub environment: EnvironmentDescription,
+    #[serde(default)]
+    pub materials: BTreeMap<String, MaterialDescription>,
+    #[serde(default)]
+    pub spheres: Vec<SphereDescription>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub triangles: Vec<TriangleDescription>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub meshes: Vec<MeshDescription>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub models: Vec<ModelDescription>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lights: Vec<LightDescription>,
+
+    /// The directory paths in the scene are relative to
+    #[serde(skip)]
+    pub directory: PathBuf,
+}
+
+impl SceneDescription {
+    /// Loads a scene description from a `.toml` or `.json` file
+    pub fn load(path: &Path) -> anyhow::Result<SceneDescription> {
+        let format = SceneFormat::from_path(path)?;
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Issue reading scene file `{}`", path.display()))?;
+
+        let mut description = SceneDescription::parse(&contents, format)
+            .with_context(|| format!("Issue parsing scene file `{}`", path.display()))?;
+        description.directory = path.parent().unwrap_or(Path::new("")).to_path_buf();
+
+        Ok(description)
+    }
+
+    /// Parses a scene description from a string (parse errors
+    /// include the line and column of the problem)
+    pub fn parse(contents: &str, format: SceneFormat) -> anyhow::Result<SceneDescription> {
+        let description = match format {
+            SceneFormat::Toml => toml::from_str(contents)?,
+            SceneFormat::Json => serde_json::from_str(contents)?,
+        };
+
+        Ok(description)
+    }
+
+    /// Saves the scene description to a `.toml` or `.json` file
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let format = SceneFormat::from_path(path)?;
+        let contents = self.to_string(format)?;
+
+        fs::write(path, contents)
+            .with_context(|| format!("Issue writing scene file `{}`", path.display()))
+    }
+
+    /// Serializes the scene description into a string
+    pub fn to_string(&self, format: SceneFormat) -> anyhow::Result<String> {
+        let contents = match format {
+            SceneFormat::Toml => toml::to_string(self)?,
+            SceneFormat::Json => serde_json::to_string_pretty(self)?,
+        };
+
+        Ok(contents)
+    }
+
+    /// Returns a hash of everything that changes how the scene looks (including
+    /// the contents of the files it loads), which leaves out how many samples
+    /// are taken so a render can be continued. The hash is the same on every
+    /// platform and Rust version (and doesn't depend on the directory the
+    /// scene was loaded from), so it can be saved
+    pub fn fingerprint(&self) -> anyhow::Result<u64> {
+        let mut description = self.clone();
+        description.render.rays_per_pixel = 0;
+        description.render.samples_per_pass = 0;
+        description.render.min_rays_per_pixel = 0;
+        description.render.noise_threshold = 0.0;
+
+        let mut hash = fnv1a(
+            FNV_OFFSET_BASIS,
+            serde_json::to_string(&description)?.as_bytes(),
+        );
+        for path in self.files()? {
+            let bytes =
+                fs::read(&path).with_context(|| format!("Issue reading `{}`", path.display()))?;
+
+            // include the length so files can't run into each other
+            hash = fnv1a(hash, &(bytes.len() as u64).to_le_bytes());
+            hash = fnv1a(hash, &bytes);
+        }
+
+        Ok(hash)
+    }
+
+    /// Returns every file the scene loads when it's built
+    fn files(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+
+        if let EnvironmentDescription::Map { path, .. } = &self.environment {
+            files.push(self.directory.join(path));
+        }
+
+        for model in &self.models {
+            files.extend(ObjModel::files(&self.directory.join(&model.path))?);
+        }
+
+        Ok(files)
+    }
+
+    /// Creates the camera described by the scene
+    pub fn camera(&self) -> anyhow::Result<Camera> {
+        let camera = &self.camera;
+        let look_at = camera
+            .look_at
+            .unwrap_or(camera.look_from + Vector3::new(0.0, 0.0, 1.0));
+
+        let lens = Lens {
+            aperture: camera.aperture,
+            focus_distance: camera
+                .focus_distance
+                .unwrap_or((look_at - camera.look_from).length()),
+            shape: camera.aperture_shape,
+        };
+
+        Camera::new(
+            camera.look_from,
+            look_at,
+            camera.up,
+            camera.vertical_fov,
+            self.render.aspect_ratio(),
+            lens,
+        )
+        .with_context(|| "Issue building the camera")
+    }
+
+    /// Creates the environment described by the scene (loading its image, if it has one)
+    pub fn environment(&self) -> anyhow::Result<Box<dyn Environment>> {
+        Ok(match &self.environment {
+            EnvironmentDescription::Gradient {
+                lower_color,
+                upper_color,
+            } => Box::new(Gradient::new(*lower_color, *upper_color)),
+            EnvironmentDescription::Map {
+                path,
+                rotation,
+                intensity,
+            } => {
+                let path = self.directory.join(path);
+                let image = load_hdr_image(&path)?;
+                Box::new(EnvironmentMap::new(image, *rotation, *intensity))
+            }
+            EnvironmentDescription::Sky {
+                sun_elevation,
+                sun_azimuth,
+                turbidity,
+                intensity,
+                ..
+            } => Box::new(PreethamSky::new(
+                sun_direction(*sun_elevation, *sun_azimuth),
+                *turbidity,
+                *intensity,
+            )?),
+        })
+    }
+
+    /// Creates the sun light matching the scene's sky (if it has one and the sun is up)
+    pub fn sun_light(&self) -> anyhow::Result<Option<DirectionalLight>> {
+        match self.environment {
+            EnvironmentDescription::Sky {
+                sun_elevation,
+                sun_azimuth,
+                turbidity,
+                sun_intensity,
+                ..
+            } if sun_intensity > 0.0 => {
+                let sky =
+                    PreethamSky::new(sun_direction(sun_elevation, sun_azimuth), turbidity, 1.0)?;
+                Ok(sky.sun_light(sun_intensity))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Looks up the material a reference points to
+    pub fn material(&self, reference: &MaterialReference) -> anyhow::Result<Arc<dyn Material>> {
+        match reference {
+            MaterialReference::Inline(description) => description.build(),
+            MaterialReference::Named(name) => match self.materials.get(name) {
+                Some(description) => description
+                    .build()
+                    .with_context(|| format!("Issue creating material `{}`", name)),
+                None => bail!("No material named `{}` in the scene's materials", name),
+            },
+        }
+    }
+
+    /// Creates all the spheres described by the scene
+    pub fn spheres(&self) -> anyhow::Result<Vec<Sphere>> {
+        self.spheres
+            .iter()
+            .enumerate()
+            .map(|(index, sphere)| {
+                let material = self
+                    .material(&sphere.material)
+                    .with_context(|| format!("Issue building sphere {}", index))?;
+
+                Ok(Sphere::new(sphere.center, sphere.radius, material))
+            })
+            .collect()
+    }
+
+    /// Creates all the triangles described by the scene
+    pub fn triangles(&self) -> anyhow::Result<Vec<Triangle>> {
+        self.triangles
+            .iter()
+            .enumerate()
+            .map(|(index, triangle)| {
+                let material = self
+                    .material(&triangle.material)
+                    .with_context(|| format!("Issue building triangle {}", index))?;
+
+                Ok(match triangle.normals {
+                    Some(normals) => Triangle::with_normals(triangle.vertices, normals, material),
+                    None => Triangle::new(triangle.vertices, material),
+                })
+            })
+            .collect()
+    }
+
+    /// Creates all the meshes described by the scene
+    pub fn meshes(&self) -> anyhow::Result<Vec<Mesh>> {
+        self.meshes
+            .iter()
+            .enumerate()
+            .map(|(index, mesh)| {
+                let material = self
+                    .material(&mesh.material)
+                    .with_context(|| format!("Issue building mesh {}", index))?;
+
+                if !mesh.normals.is_empty() && mesh.normals.len() != mesh.vertices.len() {
+                    bail!(
+                        "Mesh {} has {} vertices but {} normals",
+                        index,
+                        mesh.vertices.len(),
+                        mesh.normals.len()
+                    );
+                }
+
+                // normals share their indices with the vertices
+                let smooth = !mesh.normals.is_empty();
+                let faces = mesh
+                    .faces
+                    .iter()
+                    .map(|&positions| MeshFace {
+                        positions,
+                        normals: smooth.then_some(positions),
+                        material: 0,
+                    })
+                    .collect();
+
+                Mesh::new(
+                    mesh.vertices.clone(),
+                    mesh.normals.clone(),
+                    vec![material],
+                    faces,
+                )
+                .with_context(|| format!("Issue building mesh {}", index))
+            })
+            .collect()
+    }
+
+    /// Loads all the OBJ models in the scene
+    pub fn models(&self) -> anyhow::Result<Vec<Mesh>> {
+        self.models
+            .iter()
+            .map(|model| {
+                let default_material = match &model.material {
+                    Some(reference) => self.material(reference)?,
+                    None => Arc::new(Lambertian::new(Vector3::new(0.8, 0.8, 0.8))),
+                };
+
+                let path = self.directory.join(&model.path);
+                let mut obj_model = ObjModel::load(&path, default_material)?;
+                let context = || format!("Issue building model `{}`", path.display());
+
+                obj_model
+                    .transform(model.scale, model.offset)
+                    .with_context(context)?;
+                obj_model.into_mesh(&model.groups).with_context(context)
+            })
+            .collect()
+    }
+
+    /// Makes sure the values in the description make sense, naming the entry that doesn't
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.render.validate()?;
+
+        let camera = &self.camera;
+        let looks_at_itself = camera
+            .look_at
+            .is_some_and(|look_at| (look_at - camera.look_from).length_squared() == 0.0);
+        if looks_at_itself {
+            bail!("The camera's `look_at` can't be the same as its `look_from`");
+        }
+        if camera.up.length_squared() == 0.0 {
+            bail!("The camera's `up` direction can't be zero");
+        }
+
+        for (index, sphere) in self.spheres.iter().enumerate() {
+            if !(sphere.radius.is_finite() && sphere.radius > 0.0) {
+                bail!(
+                    "Sphere {} must have a positive radius (got {})",
+                    index,
+                    sphere.radius
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Builds the scene so it can be rendered
+    pub fn build(&self) -> anyhow::Result<Scene> {
+        self.validate()?;
+
+        let mut objects: Vec<Box<dyn Object + Send + Sync>> = Vec::new();
+        let mut lights: Vec<Box<dyn Light>> = Vec::new();
+
+        // emissive spheres are also sampled directly as lights
+        for sphere in self.spheres()? {
+            if let Some(light) = sphere.light() {
+                lights.push(Box::new(light));
+            }
+            objects.push(Box::new(sphere));
+        }
+
+        for triangle in self.triangles()? {
+            objects.push(Box::new(triangle));
+        }
+
+        for mesh in self.meshes()? {
+            objects.push(Box::new(mesh));
+        }
+
+        for model in self.models()? {
+            objects.push(Box::new(model));
+        }
+
+        if let Some(sun) = self.sun_light()? {
+            lights.push(Box::new(sun));
+        }
+
+        for (index, light) in self.lights.iter().enumerate() {
+            let (light, object) = light
+                .build()
+                .with_context(|| format!("Issue building light {}", index))?;
+
+            lights.push(light);
+            objects.extend(object);
+        }
+
+        Ok(Scene {
+            settings: self.render,
+            camera: self.camera()?,
+            environment: self.environment()?,
+            objects: Bvh::new(objects),
+            lights: LightList::new(lights),
+        })
+    }
+}
+
+impl Default for SceneDescription {
+    /// The scene rendered when no scene file is given
+    fn default() -> SceneDescription {
+        let mut materials = BTreeMap::new();
+        materials.insert(
+            "sun".to_string(),
+            MaterialDescription::Emissive {
+                color: Vector3::new(0.93, 0.95, 0.2),
+                strength: 8.0,
+            },
+        );
+        materials.insert(
+            "ground".to_string(),
+            MaterialDescription::Lambertian {
+                color: Vector3::new(0.8, 0.2, 0.2),
+            },
+        );
+
+        let mut spheres = vec![
+            SphereDescription {
+                center: Vector3::new(0.0, 16.0, 0.0),
+                radius: 8.0,
+                material: MaterialReference::Named("sun".to_string()),
+            },
+            SphereDescription {
+                center: Vector3::new(0.0, -31.0, 0.0),
+                radius: 30.0,
+                material: MaterialReference::Named("ground".to_string()),
+            },
+        ];
+
+        // a row of green spheres getting shinier towards the middle
+        let green = Vector3::new(0.2, 0.8, 0.2);
+        for (x, material) in [
+            (-10.0, MaterialDescription::Lambertian { color: green }),
+            (
+                -5.0,
+                MaterialDescription::Metal {
+                    color: green,
+                    roughness: 0.5,
+                },
+            ),
+            (0.0, MaterialDescription::Mirror { color: green }),
+            (
+                5.0,
+                MaterialDescription::Metal {
+                    color: green,
+                    roughness: 0.5,
+                },
+            ),
+            (10.0, MaterialDescription::Lambertian { color: green }),
+        ] {
+            spheres.push(SphereDescription {
+                center: Vector3::new(x, -1.0, 16.0),
+                radius: 2.0,
+                material: MaterialReference::Inline(material),
+            });
+        }
+
+        SceneDescription {
+            render: RenderSettings::default(),
+            camera: CameraDescription::default(),
+            environment: EnvironmentDescription::default(),
+            materials,
+            spheres,
+            triangles: Vec::new(),
+            meshes: Vec::new(),
+            models: Vec::new(),
+            lights: Vec::new(),
+            directory: PathBuf::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a scene using most kinds of entries, so they all go through serialization
+    const FULL_SCENE: &str = r#"
+        [render]
+        image_width = 32
+        image_height = 18
+        rays_per_pixel = 4
+        min_rays_per_pixel = 4
+
+        [camera]
+        look_from = [0.0, 1.0, -5.0]
+        look_at = [0.0, 0.0, 0.0]
+        aperture = 0.1
+
+        [environment]
+        type = "sky"
+        sun_elevation = 30.0
+
+        [materials.glass]
+        type = "dielectric"
+        refraction_index = 1.5
+
+        [[spheres]]
+        center = [0.0, 0.0, 0.0]
+        radius = 1.0
+        material = "glass"
+
+        [[spheres]]
+        center = [0.0, -101.0, 0.0]
+        radius = 100.0
+        material = { type = "principled", base_color = [0.8, 0.8, 0.8], roughness = 0.4 }
+
+        [[triangles]]
+        vertices = [[-1.0, 0.0, 2.0], [1.0, 0.0, 2.0], [0.0, 1.0, 2.0]]
+        material = { type = "metal", color = [0.9, 0.9, 0.9], roughness = 0.2 }
+
+        [[meshes]]
+        vertices = [[2.0, 0.0, 0.0], [3.0, 0.0, 0.0], [2.0, 1.0, 0.0], [3.0, 1.0, 0.0]]
+        faces = [[0, 1, 2], [1, 3, 2]]
+        material = { type = "lambertian", color = [0.2, 0.4, 0.8] }
+
+        [[lights]]
+        type = "rect"
+        corner = [-1.0, 3.0, -1.0]
+        edge_1 = [2.0, 0.0, 0.0]
+        edge_2 = [0.0, 0.0, 2.0]
+        intensity = 4.0
+    "#;
+
+    /// Serializes a description in a format, parses it back, and checks that
+    /// serializing it again gives the same text and that it still builds
+    fn assert_round_trips(description: &SceneDescription, format: SceneFormat) {
+        let serialized = description.to_string(format).unwrap();
+        let parsed = SceneDescription::parse(&serialized, format).unwrap();
+
+        assert_eq!(parsed.to_string(format).unwrap(), serialized);
+        parsed.build().unwrap();
+    }
+
+    #[test]
+    fn scenes_round_trip_through_toml_and_json() {
+        let full = SceneDescription::parse(FULL_SCENE, SceneFormat::Toml).unwrap();
+
+        for description in [SceneDescription::default(), full] {
+            assert_round_trips(&description, SceneFormat::Toml);
+            assert_round_trips(&description, SceneFormat::Json);
+        }
+    }
+
+    #[test]
+    fn fingerprints_change_with_the_files_a_scene_loads() {
+        let directory = std::env::temp_dir().join(format!("fingerprint-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("model.obj"), "mtllib model.mtl\nv 0 0 0\n").unwrap();
+        fs::write(directory.join("model.mtl"), "newmtl red\nKd 1 0 0\n").unwrap();
+
+        let mut description = SceneDescription::default();
+        description
+            .models
+            .push(ModelDescription::new(PathBuf::from("model.obj")));
+        description.directory = directory.clone();
+        let original = description.fingerprint().unwrap();
+
+        // the same scene loaded from elsewhere is the same
+        let mut moved = description.clone();
+        moved.directory = directory.join("..").join(directory.file_name().unwrap());
+        assert_eq!(moved.fingerprint().unwrap(), original);
+
+        fs::write(directory.join("model.mtl"), "newmtl red\nKd 0.9 0 0\n").unwrap();
+        let edited_material = description.fingerprint().unwrap();
+        fs::write(directory.join("model.obj"), "mtllib model.mtl\nv 0 0 1\n").unwrap();
+        let edited_model = description.fingerprint().unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_ne!(edited_material, original);
+        assert_ne!(edited_model, edited_material);
+    }
+
+    #[test]
+    fn bad_sphere_radii_are_rejected() {
+        for radius in [0.0, -1.0, f64::NAN] {
+            let mut description = SceneDescription::default();
+            description.spheres[1].radius = radius;
+
+            let error = description.build().err().unwrap().to_string();
+            assert!(error.contains("Sphere 1"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn bad_refraction_indices_are_rejected() {
+        let mut description = SceneDescription::default();
+        description.materials.insert(
+            "glass".to_string(),
+            MaterialDescription::Dielectric {
+                color: Vector3::new(1.0, 1.0, 1.0),
+                refraction_index: 0.0,
+            },
+        );
+        description.spheres[1].material = MaterialReference::Named("glass".to_string());
+
+        let error = format!("{:#}", description.build().err().unwrap());
+        assert!(error.contains("material `glass`"), "{}", error);
+    }
+
+    #[test]
+    fn cameras_looking_at_themselves_are_rejected() {
+        let mut description = SceneDescription::default();
+        description.camera.look_at = Some(description.camera.look_from);
+        assert!(description.build().is_err());
+
+        let mut description = SceneDescription::default();
+        description.camera.up = Vector3::default();
+        assert!(description.build().is_err());
+    }
+}
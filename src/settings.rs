@@ -0,0 +1,135 @@
+use crate::constants::{
+    DEFAULT_IMAGE_HEIGHT, DEFAULT_IMAGE_WIDTH, DEFAULT_MAX_BOUNCES, DEFAULT_MIN_BOUNCES,
+    DEFAULT_MIN_RAYS_PER_PIXEL, DEFAULT_RAYS_PER_PIXEL, DEFAULT_SAMPLES_PER_PASS,
+};
+use crate::filter::{Filter, FilterKind, MAX_FILTER_RADIUS, MIN_FILTER_RADIUS};
+use crate::sampler::SamplerKind;
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+/// Settings controlling the size and quality of a render
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderSettings {
+    pub image_width: u32,
+    pub image_height: u32,
+
+    /// The most rays taken per pixel (every pixel takes this many unless
+    /// the noise threshold lets it stop early)
+    pub rays_per_pixel: u32,
+
+    /// How many rays per pixel each pass of the progressive render takes
+    pub samples_per_pass: u32,
+
+    /// The fewest rays a pixel takes before the noise threshold can stop it
+    pub min_rays_per_pixel: u32,
+
+    /// Pixels less noisy than this stop taking rays (0 turns this off)
+    pub noise_threshold: f64,
+
+    /// How the random numbers for each sample are spread out
+    pub sampler: SamplerKind,
+
+    /// Picks the random numbers, so the same seed always renders the same image
+    pub seed: u64,
+
+    /// How many times paths bounce before they can be ended randomly (Russian roulette)
+    pub min_bounces: u32,
+
+    /// The most times a path can bounce (only reached by paths that never end)
+    pub max_bounces: u32,
+
+    /// The filter that blends samples into the pixels around them
+    pub filter: FilterKind,
+
+    /// How many pixels the filter reaches (the filter's usual radius if not given)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter_radius: Option<f64>,
+}
+
+impl RenderSettings {
+    /// Returns the width of the image divided by its height
+    pub fn aspect_ratio(&self) -> f64 {
+        self.image_width as f64 / self.image_height as f64
+    }
+
+    /// Returns the reconstruction filter described by the settings
+    pub fn filter(&self) -> Filter {
+        let radius = self.filter_radius.unwrap_or(self.filter.default_radius());
+        Filter::new(self.filter, radius)
+    }
+
+    /// Makes sure the settings can actually be rendered with
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.image_width < 2 || self.image_height < 2 {
+            bail!(
+                "Image must be at least 2x2 pixels (got {}x{})",
+                self.image_width,
+                self.image_height
+            );
+        }
+
+        if self.rays_per_pixel == 0 {
+            bail!("Must take at least one ray per pixel");
+        }
+
+        if self.samples_per_pass == 0 {
+            bail!("Must take at least one ray per pixel in each pass");
+        }
+
+        if self.min_bounces > self.max_bounces {
+            bail!(
+                "`min_bounces` ({}) can't be more than `max_bounces` ({}), or Russian roulette could never end a path",
+                self.min_bounces,
+                self.max_bounces
+            );
+        }
+
+        if let Some(radius) = self.filter_radius {
+            if !(MIN_FILTER_RADIUS..=MAX_FILTER_RADIUS).contains(&radius) {
+                bail!(
+                    "The filter radius must be from {} to {} pixels (got {})",
+                    MIN_FILTER_RADIUS,
+                    MAX_FILTER_RADIUS,
+                    radius
+                );
+            }
+        }
+
+        if self.min_rays_per_pixel > self.rays_per_pixel {
+            bail!(
+                "`min_rays_per_pixel` ({}) can't be more than `rays_per_pixel` ({})",
+                self.min_rays_per_pixel,
+                self.rays_per_pixel
+            );
+        }
+
+        if self.noise_threshold.is_nan() || self.noise_threshold < 0.0 {
+            bail!(
+                "The noise threshold must be 0 or more (got {})",
+                self.noise_threshold
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> RenderSettings {
+        RenderSettings {
+            image_width: DEFAULT_IMAGE_WIDTH,
+            image_height: DEFAULT_IMAGE_HEIGHT,
+            rays_per_pixel: DEFAULT_RAYS_PER_PIXEL,
+            samples_per_pass: DEFAULT_SAMPLES_PER_PASS,
+            min_rays_per_pixel: DEFAULT_MIN_RAYS_PER_PIXEL,
+            noise_threshold: 0.0,
+            sampler: SamplerKind::default(),
+            seed: 0,
+            min_bounces: DEFAULT_MIN_BOUNCES,
+            max_bounces: DEFAULT_MAX_BOUNCES,
+            filter: FilterKind::default(),
+            filter_radius: None,
+        }
+    }
+}
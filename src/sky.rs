@@ -0,0 +1,198 @@
+use crate::environment::Environment;
+use crate::lights::DirectionalLight;
+use crate::vector3::Vector3;
+use anyhow::bail;
+use std::f64::consts::PI;
+
+// the model gives luminance in kcd/m², which this scales down to
+// roughly the brightness of the default gradient sky at midday
+const SKY_LUMINANCE_SCALE: f64 = 0.05;
+
+// the irradiance of the sun before the atmosphere dims it, relative to the sky
+const SUN_IRRADIANCE: f64 = 8.0;
+
+/// The coefficients of the Perez sky luminance distribution function
+struct Perez {
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+    e: f64,
+}
+
+impl Perez {
+    /// Evaluates the distribution for a view angle from the zenith (theta)
+    /// and an angle between the view and the sun (gamma)
+    fn evaluate(&self, cos_theta: f64, gamma: f64) -> f64 {
+        let cos_gamma = gamma.cos();
+        (1.0 + self.a * (self.b / cos_theta).exp())
+            * (1.0 + self.c * (self.d * gamma).exp() + self.e * cos_gamma * cos_gamma)
+    }
+}
+
+/// Converts a CIE xyY color to linear sRGB
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Vector3 {
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+
+    Vector3::new(
+        3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z,
+        -0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z,
+        0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z,
+    )
+}
+
+/// Returns the direction towards the sun from its elevation above the
+/// horizon and its azimuth (turning from +z towards +x), both in degrees
+pub fn sun_direction(elevation: f64, azimuth: f64) -> Vector3 {
+    let elevation = elevation.to_radians();
+    let azimuth = azimuth.to_radians();
+    Vector3::new(
+        elevation.cos() * azimuth.sin(),
+        elevation.sin(),
+        elevation.cos() * azimuth.cos(),
+    )
+}
+
+/// A procedural daylight sky using the analytic model from "A Practical
+/// Analytic Model for Daylight" (Preetham, Shirley and Smits, 1999), where
+/// turbidity is how hazy the air is (2 is very clear, 10 is hazy)
+pub struct PreethamSky {
+    sun_direction: Vector3,
+    turbidity: f64,
+    intensity: f64,
+    luminance: Perez,
+    chromaticity_x: Perez,
+    chromaticity_y: Perez,
+
+    // the zenith's xyY color divided by the distribution at the zenith
+    zenith: Vector3,
+}
+
+impl PreethamSky {
+    /// Creates a new sky lit by a sun in the given direction, with
+    /// its brightness multiplied by `intensity`
+    pub fn new(
+        sun_direction: Vector3,
+        turbidity: f64,
+        intensity: f64,
+    ) -> anyhow::Result<PreethamSky> {
+        if !(1.7..=10.0).contains(&turbidity) {
+            bail!(
+                "Sky turbidity must be between 1.7 and 10 (got {})",
+                turbidity
+            );
+        }
+
+        let sun_direction = sun_direction.normalized();
+        let t = turbidity;
+
+        let luminance = Perez {
+            a: 0.1787 * t - 1.4630,
+            b: -0.3554 * t + 0.4275,
+            c: -0.0227 * t + 5.3251,
+            d: 0.1206 * t - 2.5771,
+            e: -0.0670 * t + 0.3703,
+        };
+        let chromaticity_x = Perez {
+            a: -0.0193 * t - 0.2592,
+            b: -0.0665 * t + 0.0008,
+            c: -0.0004 * t + 0.2125,
+            d: -0.0641 * t - 0.8989,
+            e: -0.0033 * t + 0.0452,
+        };
+        let chromaticity_y = Perez {
+            a: -0.0167 * t - 0.2608,
+            b: -0.0950 * t + 0.0092,
+            c: -0.0079 * t + 0.2102,
+            d: -0.0441 * t - 1.6537,
+            e: -0.0109 * t + 0.0529,
+        };
+
+        // the model breaks down once the sun sets, so keep it at the horizon
+        let sun_zenith = sun_direction
+            .y()
+            .clamp(0.0, 1.0)
+            .acos()
+            .min(PI / 2.0 - 1e-3);
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * sun_zenith);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+        let theta = sun_zenith;
+        let theta_2 = theta * theta;
+        let theta_3 = theta_2 * theta;
+        let zenith_x = t * t * (0.00166 * theta_3 - 0.00375 * theta_2 + 0.00209 * theta)
+            + t * (-0.02903 * theta_3 + 0.06377 * theta_2 - 0.03202 * theta + 0.00394)
+            + (0.11693 * theta_3 - 0.21196 * theta_2 + 0.06052 * theta + 0.25886);
+        let zenith_y = t * t * (0.00275 * theta_3 - 0.00610 * theta_2 + 0.00317 * theta)
+            + t * (-0.04214 * theta_3 + 0.08970 * theta_2 - 0.04153 * theta + 0.00516)
+            + (0.15346 * theta_3 - 0.26756 * theta_2 + 0.06670 * theta + 0.26688);
+
+        let zenith = Vector3::new(
+            zenith_x / chromaticity_x.evaluate(1.0, sun_zenith),
+            zenith_y / chromaticity_y.evaluate(1.0, sun_zenith),
+            zenith_luminance / luminance.evaluate(1.0, sun_zenith),
+        );
+
+        Ok(PreethamSky {
+            sun_direction,
+            turbidity,
+            intensity,
+            luminance,
+            chromaticity_x,
+            chromaticity_y,
+            zenith,
+        })
+    }
+
+    /// Creates a directional light for the sun, colored by how much of
+    /// its light makes it through the atmosphere (or None once it has set)
+    pub fn sun_light(&self, intensity: f64) -> Option<DirectionalLight> {
+        let elevation = self.sun_direction.y();
+        if elevation <= 0.0 {
+            return None;
+        }
+
+        // how much air the light passes through compared to straight up
+        let zenith_degrees = elevation.acos().to_degrees();
+        let air_mass = 1.0 / (elevation + 0.15 * (93.885 - zenith_degrees).powf(-1.253));
+
+        // Rayleigh and aerosol (Ångström) scattering at red, green and blue wavelengths
+        let beta = 0.04608 * self.turbidity - 0.04586;
+        let transmittance = |wavelength: f64| {
+            let rayleigh = (-0.008735 * wavelength.powf(-4.08) * air_mass).exp();
+            let aerosol = (-beta * wavelength.powf(-1.3) * air_mass).exp();
+            rayleigh * aerosol
+        };
+        let color = Vector3::new(
+            transmittance(0.680),
+            transmittance(0.550),
+            transmittance(0.440),
+        );
+
+        Some(DirectionalLight::new(
+            -self.sun_direction,
+            color * (SUN_IRRADIANCE * intensity),
+        ))
+    }
+}
+
+impl Environment for PreethamSky {
+    fn radiance(&self, direction: &Vector3) -> Vector3 {
+        // the model only covers the sky, so mirror the horizon below it
+        let cos_theta = direction.y().max(1e-3);
+        let gamma = direction
+            .normalized()
+            .dot(&self.sun_direction)
+            .clamp(-1.0, 1.0)
+            .acos();
+
+        let x = self.zenith[0] * self.chromaticity_x.evaluate(cos_theta, gamma);
+        let y = self.zenith[1] * self.chromaticity_y.evaluate(cos_theta, gamma);
+        let luminance = self.zenith[2] * self.luminance.evaluate(cos_theta, gamma);
+
+        let color = xyy_to_rgb(x, y, luminance * SKY_LUMINANCE_SCALE * self.intensity);
+        Vector3::new(color[0].max(0.0), color[1].max(0.0), color[2].max(0.0))
+    }
+}
@@ -1,19 +1,22 @@
-use crate::hit::Hit;
+use crate::aabb::Aabb;
+use crate::hit::{Hit, HIT_EPSILON};
+use crate::lights::SphereLight;
 use crate::material::Material;
 use crate::objects::Object;
 use crate::ray::Ray;
 use crate::vector3::Vector3;
+use std::sync::Arc;
 
 /// A basic sphere
 pub struct Sphere {
     center: Vector3,
     radius: f64,
-    material: Material,
+    material: Arc<dyn Material>,
 }
 
 impl Sphere {
     /// Creates a new sphere from a center, radius, and material
-    pub fn new(center: Vector3, radius: f64, material: Material) -> Sphere {
+    pub fn new(center: Vector3, radius: f64, material: Arc<dyn Material>) -> Sphere {
         Sphere {
             center,
             radius,
@@ -22,8 +25,17 @@ impl Sphere {
     }
 }
 
+impl Sphere {
+    /// Returns a light that samples this sphere, if its material is emissive
+    pub fn light(&self) -> Option<SphereLight> {
+        self.material
+            .is_emissive()
+            .then(|| SphereLight::new(self.center, self.radius, Arc::clone(&self.material)))
+    }
+}
+
 impl Object for Sphere {
-    fn get_hit(&self, ray: &Ray) -> Option<Hit> {
+    fn get_hit(&self, ray: &Ray) -> Option<Hit<'_>> {
         // get the origin of the ray relative to if this sphere's center
         let relative_ray_origin = ray.origin() - self.center;
 
@@ -44,12 +56,12 @@ impl Object for Sphere {
         // calculate the nearest hit distance from the quadratic formula
         let mut distance = -half_quadratic_b - quadratic_root.sqrt() / quadratic_a;
 
-        // negative solutions are invalid so check if the
-        // other solution is valid and if so take it
-        if distance < 0.0 {
+        // negative solutions (and ones right at the ray's origin) are
+        // invalid so check if the other solution is valid and if so take it
+        if distance < HIT_EPSILON {
             distance = -half_quadratic_b + quadratic_root.sqrt() / quadratic_a;
 
-            if distance < 0.0 {
+            if distance < HIT_EPSILON {
                 return None;
             }
         }
@@ -65,11 +77,12 @@ impl Object for Sphere {
             hit_point,
             normal_vector,
             outside_hit,
-            self.material,
+            self.material.as_ref(),
         ))
     }
 
-    fn material(self) -> Material {
-        self.material
+    fn bounding_box(&self) -> Aabb {
+        let radius_vector = Vector3::new(self.radius, self.radius, self.radius);
+        Aabb::new(self.center - radius_vector, self.center + radius_vector)
     }
 }
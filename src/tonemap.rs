@@ -0,0 +1,97 @@
+use crate::image::Image;
+use crate::vector3::Vector3 as Color;
+use clap::ValueEnum;
+
+/// The operators that can compress unbounded colors into the 0 to 1 range
+#[derive(Copy, Clone, ValueEnum)]
+pub enum ToneMapOperator {
+    /// Cuts off anything brighter than 1
+    Clamp,
+    /// Smoothly compresses bright colors with c / (1 + c)
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve
+    Aces,
+}
+
+impl ToneMapOperator {
+    /// Maps a single linear color channel
+    fn map(self, value: f64) -> f64 {
+        let value = value.max(0.0);
+
+        match self {
+            ToneMapOperator::Clamp => value.min(1.0),
+            ToneMapOperator::Reinhard => value / (1.0 + value),
+            ToneMapOperator::Aces => {
+                let mapped =
+                    (value * (2.51 * value + 0.03)) / (value * (2.43 * value + 0.59) + 0.14);
+                mapped.clamp(0.0, 1.0)
+            }
+        }
+    }
+}
+
+/// Converts a linear color channel from 0 to 1 to the sRGB transfer curve
+pub fn linear_to_srgb(value: f64) -> f64 {
+    if value <= 0.0031308 {
+        12.92 * value
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The post-processing applied to a rendered image before it is quantized
+#[derive(Copy, Clone)]
+pub struct ToneMapper {
+    operator: ToneMapOperator,
+    exposure: f64,
+    srgb: bool,
+}
+
+impl ToneMapper {
+    /// Creates a new tone mapper from an operator, an exposure
+    /// adjustment in stops, and whether to apply the sRGB curve
+    pub fn new(operator: ToneMapOperator, exposure: f64, srgb: bool) -> ToneMapper {
+        ToneMapper {
+            operator,
+            exposure,
+            srgb,
+        }
+    }
+
+    /// Returns how much the exposure multiplies colors by
+    fn exposure_scale(&self) -> f64 {
+        2f64.powf(self.exposure)
+    }
+
+    /// Tone maps a single linear color
+    pub fn map_color(&self, color: &Color) -> Color {
+        let exposed = *color * self.exposure_scale();
+        let mut mapped = Color::default();
+
+        for channel in 0..3 {
+            mapped[channel] = self.operator.map(exposed[channel]);
+
+            if self.srgb {
+                mapped[channel] = linear_to_srgb(mapped[channel]);
+            }
+        }
+
+        mapped
+    }
+
+    /// Tone maps every pixel of an image
+    pub fn apply(&self, image: &mut Image) {
+        for pixel in image.pixels_mut() {
+            *pixel = self.map_color(pixel);
+        }
+    }
+
+    /// Only applies the exposure to every pixel of an image, leaving
+    /// its colors linear and unclamped (for floating point formats)
+    pub fn apply_exposure(&self, image: &mut Image) {
+        let scale = self.exposure_scale();
+        for pixel in image.pixels_mut() {
+            *pixel *= scale;
+        }
+    }
+}
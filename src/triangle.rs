@@ -0,0 +1,175 @@
+use crate::aabb::Aabb;
+use crate::hit::{Hit, HIT_EPSILON};
+use crate::material::Material;
+use crate::objects::Object;
+use crate::ray::Ray;
+use crate::vector3::Vector3;
+use std::sync::Arc;
+
+// rays closer than this (relative) to being parallel with a triangle miss it
+const PARALLEL_EPSILON: f64 = 1e-12;
+
+/// Finds where a ray hits a triangle using the Möller–Trumbore algorithm,
+/// returning the distance of the hit and the barycentric weights (u, v)
+/// of the second and third vertices at the hit point
+pub fn intersect_triangle(ray: &Ray, vertices: &[Vector3; 3]) -> Option<(f64, f64, f64)> {
+    let edge_1 = vertices[1] - vertices[0];
+    let edge_2 = vertices[2] - vertices[0];
+
+    let p = ray.direction().cross(&edge_2);
+    let determinant = edge_1.dot(&p);
+
+    // the ray is parallel to the triangle (the determinant grows with the size
+    // of the triangle, so the tolerance is scaled by the lengths of its edges)
+    if determinant.abs() <= PARALLEL_EPSILON * edge_1.length() * edge_2.length() {
+        return None;
+    }
+
+    let inverse_determinant = 1.0 / determinant;
+    let relative_origin = ray.origin() - vertices[0];
+
+    let u = relative_origin.dot(&p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = relative_origin.cross(&edge_1);
+
+    let v = ray.direction().dot(&q) * inverse_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let distance = edge_2.dot(&q) * inverse_determinant;
+    if distance < HIT_EPSILON {
+        return None;
+    }
+
+    Some((distance, u, v))
+}
+
+/// Creates the hit for a ray hitting a triangle, interpolating the vertex
+/// normals (if there are any) at the barycentric position (u, v)
+pub fn triangle_hit<'a>(
+    ray: &Ray,
+    (distance, u, v): (f64, f64, f64),
+    vertices: &[Vector3; 3],
+    normals: Option<&[Vector3; 3]>,
+    material: &'a dyn Material,
+) -> Hit<'a> {
+    // the winding order of the vertices decides which side is outside
+    let geometric_normal = (vertices[1] - vertices[0])
+        .cross(&(vertices[2] - vertices[0]))
+        .normalized();
+
+    // interpolate the vertex normals, making sure they stay
+    // on the same side as the actual surface of the triangle
+    let normal = match normals {
+        None => geometric_normal,
+        Some(normals) => {
+            let interpolated =
+                ((1.0 - u - v) * normals[0] + u * normals[1] + v * normals[2]).normalized();
+
+            if interpolated.dot(&geometric_normal) < 0.0 {
+                -interpolated
+            } else {
+                interpolated
+            }
+        }
+    };
+
+    let outside_hit = geometric_normal.dot(&ray.direction()) <= 0.0;
+
+    Hit::new(distance, ray.at(distance), normal, outside_hit, material)
+}
+
+/// Returns the bounding box of a triangle
+pub fn triangle_bounding_box(vertices: &[Vector3; 3]) -> Aabb {
+    vertices
+        .iter()
+        .fold(Aabb::empty(), |bounds, vertex| bounds.grow(vertex))
+}
+
+/// A single triangle (vertices are counter-clockwise when seen from outside)
+pub struct Triangle {
+    vertices: [Vector3; 3],
+    normals: Option<[Vector3; 3]>,
+    material: Arc<dyn Material>,
+}
+
+impl Triangle {
+    /// Creates a new flat-shaded triangle from three vertices and a material
+    pub fn new(vertices: [Vector3; 3], material: Arc<dyn Material>) -> Triangle {
+        Triangle {
+            vertices,
+            normals: None,
+            material,
+        }
+    }
+
+    /// Creates a new smooth-shaded triangle with a normal for each vertex
+    pub fn with_normals(
+        vertices: [Vector3; 3],
+        normals: [Vector3; 3],
+        material: Arc<dyn Material>,
+    ) -> Triangle {
+        Triangle {
+            vertices,
+            normals: Some(normals.map(|normal| normal.normalized())),
+            material,
+        }
+    }
+}
+
+impl Object for Triangle {
+    fn get_hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        let intersection = intersect_triangle(ray, &self.vertices)?;
+
+        Some(triangle_hit(
+            ray,
+            intersection,
+            &self.vertices,
+            self.normals.as_ref(),
+            self.material.as_ref(),
+        ))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        triangle_bounding_box(&self.vertices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiny_triangles_are_hit() {
+        for size in [1e-7, 1e-4, 1.0, 1e4] {
+            let vertices = [
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(size, 0.0, 0.0),
+                Vector3::new(0.0, size, 0.0),
+            ];
+            let ray = Ray::new(
+                Vector3::new(size * 0.25, size * 0.25, 1.0),
+                Vector3::new(0.0, 0.0, -1.0),
+            );
+
+            let (distance, u, v) = intersect_triangle(&ray, &vertices).unwrap();
+            assert!((distance - 1.0).abs() < 1e-9);
+            assert!((u - 0.25).abs() < 1e-6 && (v - 0.25).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn parallel_rays_miss() {
+        let vertices = [
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+        ];
+        let ray = Ray::new(Vector3::new(-1.0, 0.25, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        assert!(intersect_triangle(&ray, &vertices).is_none());
+    }
+}
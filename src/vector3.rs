@@ -1,8 +1,12 @@
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
 use std::ops;
+use std::str::FromStr;
 
 /// A basic vector3 that can represent actual
-/// vectors, points, or colors
-#[derive(Copy, Clone)]
+/// vectors, points, or colors (serialized as [x, y, z])
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Vector3 {
     terms: [f64; 3],
 }
@@ -55,6 +59,11 @@ impl Vector3 {
         self.length_squared().sqrt()
     }
 
+    /// Returns how bright the vector is as a linear color (Rec. 709 luminance)
+    pub fn luminance(self) -> f64 {
+        0.2126 * self[0] + 0.7152 * self[1] + 0.0722 * self[2]
+    }
+
     /// Returns the dot product of two vectors
     pub fn dot(self, other: &Vector3) -> f64 {
         self[0] * other[0] + self[1] * other[1] + self[2] * other[2]
@@ -69,11 +78,39 @@ impl Vector3 {
         )
     }
 
+    /// Returns two normalized vectors that are perpendicular to each
+    /// other and to a normalized vector (for building local coordinates)
+    pub fn orthonormal_basis(self) -> (Vector3, Vector3) {
+        // Duff et al.'s branchless construction
+        let sign = 1f64.copysign(self[2]);
+        let a = -1.0 / (sign + self[2]);
+        let b = self[0] * self[1] * a;
+
+        (
+            Vector3::new(
+                1.0 + sign * self[0] * self[0] * a,
+                sign * b,
+                -sign * self[0],
+            ),
+            Vector3::new(b, sign + self[1] * self[1] * a, -self[1]),
+        )
+    }
+
     /// Reflects a Vector3 across a normalized vector
     pub fn reflect_across(self, normal: &Vector3) -> Vector3 {
         self - 2.0 * self.dot(normal) * *normal
     }
 
+    /// Refracts a normalized Vector3 through a surface with Snell's law, given the
+    /// normal facing against the vector and the ratio of the refraction index
+    /// being left over the one being entered (assumes the refraction is possible)
+    pub fn refract(self, normal: &Vector3, refraction_ratio: f64) -> Vector3 {
+        let cos_theta = (-self).dot(normal).min(1.0);
+        let perpendicular = refraction_ratio * (self + cos_theta * *normal);
+        let parallel = -(1.0 - perpendicular.length_squared()).abs().sqrt() * *normal;
+        perpendicular + parallel
+    }
+
     /// Linearly interpolates between two vectors
     /// (assumes self is starting vector)
     pub fn lerp(self, other: &Vector3, t: f64) -> Vector3 {
@@ -81,6 +118,28 @@ impl Vector3 {
     }
 }
 
+impl FromStr for Vector3 {
+    type Err = anyhow::Error;
+
+    /// Parses a vector written as three comma-separated numbers (`x,y,z`)
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let terms = string
+            .split(',')
+            .map(|term| {
+                term.trim()
+                    .parse::<f64>()
+                    .with_context(|| format!("`{}` is not a number", term.trim()))
+            })
+            .collect::<anyhow::Result<Vec<f64>>>()?;
+
+        if terms.len() != 3 {
+            bail!("Expected 3 comma-separated numbers, got {}", terms.len());
+        }
+
+        Ok(Vector3::new(terms[0], terms[1], terms[2]))
+    }
+}
+
 //
 // Operator overloading
 //